@@ -1,18 +1,51 @@
-use std::sync::Arc;
+use std::{
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use tauri::async_runtime::Mutex;
 use tauri_plugin_dialog::DialogExt;
 use {
     scheduler::{
         models::{csv, ScheduleModel},
-        Schedule,
+        moves::Mix,
+        rand::{self, rngs::StdRng, SeedableRng},
+        Options, Progress, Schedule,
     },
-    tauri::{AppHandle, Manager},
+    tauri::{AppHandle, Emitter, Manager},
 };
 
+#[derive(Clone, Copy, serde::Serialize)]
+struct CostReport {
+    hard: u64,
+    soft: u64,
+}
+
+#[derive(Clone, Copy, serde::Serialize)]
+struct OptimizeReport {
+    cost: CostReport,
+    lower_bound: CostReport,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct ProgressReport {
+    iteration: usize,
+    temperature: f64,
+    cost: CostReport,
+    best_cost: CostReport,
+    accepted: usize,
+    rejected: usize,
+}
+
 struct State {
     schedule: Arc<Mutex<Option<Schedule>>>,
     file: Arc<Mutex<Option<String>>>,
+    slot_labels: Arc<AtomicBool>,
+    line_labels: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
 }
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -22,37 +55,108 @@ async fn optimize_schedule(
     aging: usize,
     shuffling: bool,
     greedily: bool,
-) -> Result<u64, String> {
+    jobs: usize,
+) -> Result<OptimizeReport, String> {
     // let mut schedule: Schedule = serde_json::from_str::<ScheduleModel>(&file).unwrap().into();
 
     let state = app.state::<State>();
+    state.stop.store(false, Ordering::Relaxed);
     let mut schedule = state.schedule.lock().await;
     if let None = *schedule {
         let file = state.file.lock().await;
         let file = file.as_ref().ok_or("file was not selected")?;
         let mut reader = csv::ReaderBuilder::new()
-            .has_headers(false)
+            .has_headers(state.slot_labels.load(Ordering::Relaxed))
+            .flexible(true)
             .from_reader(String::as_bytes(&file));
-        *schedule = Some(Schedule::new(
-            ScheduleModel::deserialize_csv(&mut reader).unwrap().into(),
-        ));
+        let line_labels = state.line_labels.load(Ordering::Relaxed);
+        let model =
+            ScheduleModel::deserialize_csv(&mut reader, line_labels).map_err(|e| e.to_string())?;
+        *schedule = Some(Schedule::from(model));
     };
     let time = std::time::Instant::now();
     let schedule = unsafe { schedule.as_mut().unwrap_unchecked() };
-    schedule.optimize(0.999, aging, shuffling, greedily, || ());
+    let seed = rand::random();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let options = Options {
+        lambda: 0.999,
+        aging,
+        shuffling,
+        greedily,
+        moves: Mix::default_for(schedule),
+        time_limit: None,
+    };
+    let last_report = std::sync::Mutex::new(time);
+    let tick = |progress: &Progress| {
+        if let Ok(mut last_report) = last_report.try_lock() {
+            if last_report.elapsed().as_millis() >= 100 {
+                *last_report = std::time::Instant::now();
+                let _ = app.emit(
+                    "optimize-progress",
+                    ProgressReport {
+                        iteration: progress.iteration,
+                        temperature: progress.temperature,
+                        cost: CostReport {
+                            hard: progress.cost.hard,
+                            soft: progress.cost.soft,
+                        },
+                        best_cost: CostReport {
+                            hard: progress.best_cost.hard,
+                            soft: progress.best_cost.soft,
+                        },
+                        accepted: progress.accepted,
+                        rejected: progress.rejected,
+                    },
+                );
+            }
+        }
+        if state.stop.load(Ordering::Relaxed) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    };
+    let outcome = if jobs > 1 {
+        let run = scheduler::parallel::optimize(schedule, jobs, seed, &options, |_, progress| {
+            tick(progress)
+        });
+        println!("best chain: job {} (seed {})", run.job, run.seed);
+        *schedule = run.schedule;
+        run.outcome
+    } else {
+        schedule.optimize(&mut rng, &options, tick)
+    };
 
-    let cost = schedule.cost;
+    let cost = outcome.best_cost;
+    let lower_bound = schedule.lower_bound();
     let dur = time.elapsed();
 
+    println!("seed: {}", seed);
     println!("results cost: {}", cost);
+    println!("lower bound: {}", lower_bound);
+    println!("final cost: {}", outcome.final_cost);
     println!("calculation time: {}", dur.as_secs_f32());
 
-    Ok(cost)
+    Ok(OptimizeReport {
+        cost: CostReport {
+            hard: cost.hard,
+            soft: cost.soft,
+        },
+        lower_bound: CostReport {
+            hard: lower_bound.hard,
+            soft: lower_bound.soft,
+        },
+    })
+}
+
+#[tauri::command]
+async fn stop_optimization(app: AppHandle) {
+    app.state::<State>().stop.store(true, Ordering::Relaxed);
 }
 
 #[tauri::command]
 async fn download_file(app: AppHandle) -> Result<(), String> {
-    let scheme = {
+    let schedule = {
         let state = app.state::<State>();
         let schedule = state.schedule.lock().await;
         let schedule = if let Some(s) = &*schedule {
@@ -60,25 +164,26 @@ async fn download_file(app: AppHandle) -> Result<(), String> {
         } else {
             return Err("File is not optimized".to_string());
         };
-        schedule.scheme.clone()
+        schedule.clone()
     };
     app.dialog()
         .file()
         .add_filter("Schdedule table", &["csv", "txt"])
         .save_file(|path| {
             let path = path.unwrap();
-            let mut writer = match csv::WriterBuilder::new().has_headers(false).from_path(
-                if let Some(p) = &path.as_path() {
+            let mut writer = match csv::WriterBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_path(if let Some(p) = &path.as_path() {
                     p
                 } else {
                     return;
-                },
-            ) {
+                }) {
                 Ok(w) => w,
                 Err(_) => return,
             };
 
-            ScheduleModel::from(scheme)
+            ScheduleModel::from(schedule)
                 .serialize_csv(&mut writer)
                 .unwrap();
         });
@@ -86,9 +191,16 @@ async fn download_file(app: AppHandle) -> Result<(), String> {
 }
 
 #[tauri::command]
-async fn select_file(app: AppHandle, file: String) -> Result<(), ()> {
+async fn select_file(
+    app: AppHandle,
+    file: String,
+    slot_labels: bool,
+    line_labels: bool,
+) -> Result<(), ()> {
     let state = app.state::<State>();
     *state.file.lock().await = Some(file);
+    state.slot_labels.store(slot_labels, Ordering::Relaxed);
+    state.line_labels.store(line_labels, Ordering::Relaxed);
     *state.schedule.lock().await = None;
     Ok(())
 }
@@ -100,6 +212,9 @@ pub fn run() {
             app.manage(State {
                 schedule: Arc::new(Mutex::new(None)),
                 file: Arc::new(Mutex::new(None)),
+                slot_labels: Arc::new(AtomicBool::new(false)),
+                line_labels: Arc::new(AtomicBool::new(false)),
+                stop: Arc::new(AtomicBool::new(false)),
             });
             Ok(())
         })
@@ -108,6 +223,7 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             optimize_schedule,
+            stop_optimization,
             download_file,
             select_file
         ])
@@ -1,4 +1,5 @@
 const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
 
 document.addEventListener("DOMContentLoaded", function () {
     console.log("st");
@@ -8,25 +9,64 @@ document.addEventListener("DOMContentLoaded", function () {
 
     const optimizeButton = document.querySelector("#optimizeButton");
     const downloadButton = document.querySelector("#downloadButton");
+    const updateButton = document.querySelector("#updateButton");
+    const stopButton = document.querySelector("#stopButton");
     const resultsBlock = document.querySelector("#resultsBlock");
 
+    listen("optimize-progress", (event) => {
+        const p = event.payload;
+        costField.innerHTML =
+            `${p.cost.hard} hard / ${p.cost.soft} soft ` +
+            `(best ${p.best_cost.hard} / ${p.best_cost.soft}, ` +
+            `t ${p.temperature.toExponential(2)}, ` +
+            `iteration ${p.iteration})`;
+    });
+
     async function optimizeFile() {
         console.log(await fileInput.files[0].text());
 
-        let cost = await invoke("optimize_schedule", {
-            aging: Number(document.querySelector(".aging-input").value),
-            shuffling: document.querySelector("#checkboxShuffling").checked,
-            greedily: document.querySelector("#checkboxGreedily").checked,
-        });
+        updateButton.style = "display: none";
+        stopButton.style = "";
+        let report;
+        try {
+            report = await invoke("optimize_schedule", {
+                aging: Number(document.querySelector(".aging-input").value),
+                shuffling: document.querySelector("#checkboxShuffling").checked,
+                greedily: document.querySelector("#checkboxGreedily").checked,
+                jobs: Number(document.querySelector(".jobs-input").value),
+            });
+        } finally {
+            stopButton.style = "display: none";
+            updateButton.style = "";
+        }
 
-        costField.innerHTML = cost;
-        if (cost == 0) {
+        const { cost, lower_bound: bound } = report;
+        const optimal = cost.hard == bound.hard && cost.soft == bound.soft;
+        costField.innerHTML =
+            `${cost.hard} hard / ${cost.soft} soft, ` +
+            `lower bound ${bound.hard} hard / ${bound.soft} soft` +
+            (optimal
+                ? " \u2014 optimal"
+                : ` (gap ${cost.hard - bound.hard} / ${Math.max(cost.soft - bound.soft, 0)})`);
+        if (cost.hard == 0) {
             downloadButton.classList.add("succesful");
         } else {
             downloadButton.classList.remove("succesful");
         }
     }
 
+    // Labels change how the file is read, so it is selected again when they are toggled.
+    async function selectFile() {
+        if (!(fileInput.files && fileInput.files[0])) {
+            return;
+        }
+        await invoke("select_file", {
+            file: await fileInput.files[0].text(),
+            slotLabels: document.querySelector("#checkboxSlotLabels").checked,
+            lineLabels: document.querySelector("#checkboxLineLabels").checked,
+        });
+    }
+
     function enableResultsBlock() {
         optimizeButton.style = "display: none";
         resultsBlock.style = "";
@@ -55,18 +95,30 @@ document.addEventListener("DOMContentLoaded", function () {
             fileInfo.className = "file-info";
             fileSelector.querySelector("div.text-center").appendChild(fileInfo);
         }
-        await invoke("select_file", { file: await fileInput.files[0].text() });
+        await selectFile();
     });
+    for (const checkbox of ["#checkboxSlotLabels", "#checkboxLineLabels"]) {
+        document
+            .querySelector(checkbox)
+            .addEventListener("change", async function () {
+                disableResultsBlock();
+                await selectFile();
+            });
+    }
 
     optimizeButton.addEventListener("click", async (e) => {
         e.preventDefault();
         enableResultsBlock();
         optimizeFile();
     });
-    document.querySelector("#updateButton").addEventListener("click", (e) => {
+    updateButton.addEventListener("click", (e) => {
         e.preventDefault();
         optimizeFile();
     });
+    stopButton.addEventListener("click", async (e) => {
+        e.preventDefault();
+        await invoke("stop_optimization");
+    });
     downloadButton.addEventListener("click", async (e) => {
         e.preventDefault();
         await invoke("download_file");
@@ -1,13 +1,28 @@
 use {
-    clap::{Parser, arg},
-    indicatif::ProgressBar,
+    clap::{Parser, ValueEnum},
+    indicatif::{ProgressBar, ProgressStyle},
     scheduler::{
-        Schedule,
-        models::{Result, ScheduleModel, csv},
+        Objective, Options, Progress, Schedule, Weight, analysis,
+        constraints::{LeaderGaps, Unavailability},
+        exact,
+        models::{
+            AliasModel, AvailabilityModel, CapacityModel, CompatibilityModel, Normalization,
+            Normalizer, Result, ScheduleModel, csv,
+        },
+        moves::Mix,
+        rand::{self, SeedableRng, rngs::StdRng},
+        tabu, tempering,
+    },
+    std::{
+        ops::ControlFlow,
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
     },
-    std::path::PathBuf,
 };
 
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 fn validate_input_path(s: &str) -> std::result::Result<PathBuf, String> {
     let path = PathBuf::from(s);
     if path.exists() {
@@ -26,6 +41,31 @@ fn validate_lambda_opt(s: &str) -> std::result::Result<f64, String> {
     }
 }
 
+fn validate_duration(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let (value, unit) = s.split_at(
+        s.find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len()),
+    );
+    let value = value.parse::<f64>().map_err(|e| format!("{e}"))?;
+    let secs = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" | "min" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err("Unknown time unit (expected ms, s, m or h)".to_string()),
+    };
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Solver {
+    Anneal,
+    Tempering,
+    Tabu,
+    Exact,
+}
+
 #[derive(Parser)]
 struct Args {
     #[arg(
@@ -33,6 +73,16 @@ struct Args {
         help = "Input file (must exist)"
     )]
     input_path: PathBuf,
+    #[arg(
+        long,
+        help = "Read slot labels (e.g. times) from the first row of the input and write them back"
+    )]
+    slot_labels: bool,
+    #[arg(
+        long,
+        help = "Read line names (e.g. classes or rooms) from the first column of the input"
+    )]
+    line_labels: bool,
 
     #[arg(
         short,
@@ -41,6 +91,43 @@ struct Args {
     )]
     output_path: PathBuf,
 
+    #[arg(
+        short,
+        long,
+        value_parser = validate_input_path,
+        help = "Leader unavailability file: leader,slot-range,... with slots numbered from 1, e.g. 3 or 2-4 (must exist)"
+    )]
+    unavailable: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = validate_input_path,
+        help = "Lines each movable (*) event may go to: event,line,... with lines numbered from 1 (must exist)"
+    )]
+    allowed_lines: Option<PathBuf>,
+    #[arg(
+        long,
+        value_parser = validate_input_path,
+        help = "Events each leader or resource can take part in at once: name,capacity (must exist)"
+    )]
+    pools: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Most slots a line may hold as movable events relocate (default: the longest line)"
+    )]
+    capacity: Option<usize>,
+    #[arg(
+        long,
+        help = "Treat names differing only in these as one: case, whitespace, unicode or all"
+    )]
+    normalize: Option<Normalization>,
+    #[arg(
+        long,
+        value_parser = validate_input_path,
+        help = "Spellings of the same leader or resource: canonical,variant,... (must exist)"
+    )]
+    aliases: Option<PathBuf>,
+
     #[arg(short, long, value_parser = validate_lambda_opt)]
     lamda_opt: Option<f64>,
     #[arg(short, long)]
@@ -49,39 +136,303 @@ struct Args {
     shuffling: bool,
     #[arg(short, long)]
     greedily: bool,
+    #[arg(
+        long,
+        help = "Move kinds and their relative probabilities, e.g. swap=2,insert=1,rotate=1,block=1"
+    )]
+    moves: Option<Mix>,
+    #[arg(long, value_enum, default_value_t = Solver::Anneal)]
+    solver: Solver,
+    #[arg(
+        short,
+        long,
+        default_value_t = 1,
+        help = "Independent optimization chains to run in parallel"
+    )]
+    jobs: usize,
+    #[arg(
+        long,
+        default_value_t = tempering::REPLICAS_DEFAULT,
+        help = "Replicas for the tempering solver"
+    )]
+    replicas: usize,
+    #[arg(
+        long,
+        default_value_t = tabu::TENURE_DEFAULT,
+        help = "Iterations a reversed swap stays forbidden for the tabu solver"
+    )]
+    tenure: usize,
+    #[arg(long, help = "Seed for the optimizer (random if omitted)")]
+    seed: Option<u64>,
+    #[arg(
+        long,
+        value_parser = validate_duration,
+        help = "Stop after this long, e.g. 30s or 5m"
+    )]
+    time_limit: Option<Duration>,
+
+    #[arg(long, help = "Soft penalty per idle slot between a leader's events")]
+    gaps: Option<u64>,
+    #[arg(
+        long,
+        help = "Trade hard violations against soft penalty with this weight instead of comparing lexicographically"
+    )]
+    hard_weight: Option<f64>,
+
+    #[arg(long, help = "Only report problems no arrangement can fix, then exit")]
+    check: bool,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
     let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
+        .has_headers(args.slot_labels)
+        .flexible(true)
         .from_path(&args.input_path)
         .unwrap();
+    let aliases = match &args.aliases {
+        Some(path) => {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_path(path)
+                .unwrap();
+            Some(AliasModel::deserialize_csv(&mut reader)?)
+        }
+        None => None,
+    };
+    let mut normalizer = Normalizer::new(args.normalize.unwrap_or_default(), aliases);
+
     // let mut schedule: Schedule = serde_json::from_str::<ScheduleModel>(&file).unwrap().into();
-    let mut schedule = Schedule::new(ScheduleModel::deserialize_csv(&mut reader)?.into());
+    let mut model = ScheduleModel::deserialize_csv(&mut reader, args.line_labels)?;
+    model.normalize(&mut normalizer);
+    let mut schedule = Schedule::from(model);
+
+    if let Some(path) = &args.unavailable {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)
+            .unwrap();
+        let mut model = AvailabilityModel::deserialize_csv(&mut reader)?;
+        model.normalize(&mut normalizer);
+        let (unavailable, unknown) = model.resolve(&schedule.registry);
+        for leader in unknown {
+            println!("warning: unknown leader in {}: {leader}", path.display());
+        }
+        schedule.add_constraint(Unavailability::new(unavailable), Weight::Hard(1));
+    }
+
+    if let Some(path) = &args.pools {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)
+            .unwrap();
+        let mut model = CapacityModel::deserialize_csv(&mut reader)?;
+        model.normalize(&mut normalizer);
+        let (capacities, unknown) = model.resolve(&schedule.registry);
+        for name in unknown {
+            println!(
+                "warning: unknown leader or resource in {}: {name}",
+                path.display()
+            );
+        }
+        schedule.capacities = capacities;
+        schedule.update();
+    }
+
+    if let Some(path) = &args.allowed_lines {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)
+            .unwrap();
+        let model = CompatibilityModel::deserialize_csv(&mut reader)?;
+        let (allowed, unknown) = model.resolve(&schedule.scheme)?;
+        for event in unknown {
+            println!("warning: unknown event in {}: {event}", path.display());
+        }
+        schedule.relocation.allowed = allowed;
+    }
+    if let Some(capacity) = args.capacity {
+        schedule.relocation.capacity.fill(capacity);
+    }
+
+    if let Some(weight) = args.gaps {
+        schedule.add_constraint(LeaderGaps, Weight::Soft(weight));
+    }
+
+    if let Some(weight) = args.hard_weight {
+        schedule.objective = Objective::Weighted(weight);
+    }
+
+    for (name, variants) in normalizer.merged() {
+        let variants: Vec<&str> = variants.iter().map(|v| &**v).collect();
+        println!("merged: {name} <- {}", variants.join(", "));
+    }
+
+    for issue in analysis::analyze(&schedule) {
+        println!("warning: {issue}");
+    }
+    if args.check {
+        return Ok(());
+    }
 
     let aging = args.aging_opt.unwrap_or(scheduler::AGING_OPT_DEFAULT);
 
-    let pb = ProgressBar::new(aging as u64);
+    let pb = ProgressBar::new((aging * args.jobs.max(1)) as u64).with_style(
+        ProgressStyle::with_template("{wide_bar} {pos}/{len} {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar()),
+    );
 
-    let time = std::time::Instant::now();
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
 
-    schedule.optimize(
-        args.lamda_opt.unwrap_or(scheduler::LAMBDA_OPT_DEFAULT),
+    let options = Options {
+        lambda: args.lamda_opt.unwrap_or(scheduler::LAMBDA_OPT_DEFAULT),
         aging,
-        args.shuffling,
-        args.greedily,
-        || pb.inc(1),
-    );
+        shuffling: args.shuffling,
+        greedily: args.greedily,
+        moves: args.moves.unwrap_or_else(|| Mix::default_for(&schedule)),
+        time_limit: args.time_limit,
+    };
+
+    unsafe {
+        signal_hook_registry::register(libc::SIGINT, || {
+            INTERRUPTED.store(true, Ordering::Relaxed)
+        })?;
+    }
+
+    let time = std::time::Instant::now();
+
+    let tick = |progress: &Progress, step: usize| {
+        pb.inc(step as u64);
+        if progress.iteration % 256 < step {
+            pb.set_message(format!(
+                "cost {} | best {} | t {:.3e} | accepted {} rejected {}",
+                progress.cost,
+                progress.best_cost,
+                progress.temperature,
+                progress.accepted,
+                progress.rejected
+            ));
+        }
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    };
+
+    let outcome = match args.solver {
+        Solver::Anneal if args.jobs > 1 => {
+            let run =
+                scheduler::parallel::optimize(&schedule, args.jobs, seed, &options, |_, p| {
+                    tick(p, 1)
+                });
+            schedule = run.schedule;
+            println!("best chain: job {} (seed {})", run.job, run.seed);
+            run.outcome
+        }
+        Solver::Anneal => schedule.optimize(&mut rng, &options, |p| tick(p, 1)),
+        Solver::Tempering => {
+            let options = tempering::Options {
+                replicas: args.replicas,
+                steps: aging,
+                shuffling: args.shuffling,
+                greedily: args.greedily,
+                moves: options.moves,
+                time_limit: args.time_limit,
+                ..Default::default()
+            };
+            tempering::optimize(&mut schedule, &mut rng, &options, |p| {
+                tick(p, options.exchange_interval)
+            })
+        }
+        Solver::Tabu => {
+            let options = tabu::Options {
+                tenure: args.tenure,
+                iterations: aging,
+                shuffling: args.shuffling,
+                time_limit: args.time_limit,
+            };
+            tabu::optimize(&mut schedule, &mut rng, &options, |p| tick(p, 1))
+        }
+        Solver::Exact => {
+            let solution = exact::solve(&schedule, args.time_limit, |nodes| {
+                pb.set_message(format!("nodes {nodes}"));
+                if INTERRUPTED.load(Ordering::Relaxed) {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+            pb.finish_and_clear();
+            match solution.verdict {
+                exact::Verdict::Feasible(scheme) => {
+                    schedule.scheme = scheme;
+                    schedule.update();
+                    println!("feasible: found an arrangement without hard violations");
+                }
+                exact::Verdict::Infeasible => {
+                    println!("infeasible: no arrangement without hard violations exists")
+                }
+                exact::Verdict::Unknown => println!(
+                    "unknown: stopped before the search finished, or movable events may need to relocate"
+                ),
+            }
+            println!("results cost: {}", schedule.cost);
+            report_violations(&schedule);
+            report_bound(&schedule);
+            println!("nodes: {}", solution.nodes);
+            println!("calculation time: {}", time.elapsed().as_secs_f32());
+
+            return write_schedule(&args.output_path, schedule);
+        }
+    };
     pb.finish();
+    if INTERRUPTED.load(Ordering::Relaxed) {
+        println!("interrupted, writing the best schedule found so far");
+    }
     let dur = time.elapsed();
-    println!("results cost: {}", schedule.cost);
+    println!("seed: {seed}");
+    println!("results cost: {}", outcome.best_cost);
+    report_violations(&schedule);
+    report_bound(&schedule);
+    println!("final cost: {}", outcome.final_cost);
+    println!("iterations: {}", outcome.iterations);
     println!("calculation time: {}", dur.as_secs_f32());
 
+    write_schedule(&args.output_path, schedule)
+}
+
+fn report_violations(schedule: &Schedule) {
+    let counts: Vec<String> = schedule
+        .constraints
+        .iter()
+        .zip(&schedule.violations)
+        .map(|(constraint, violations)| format!("{} {violations}", constraint.name()))
+        .collect();
+    println!("violations: {}", counts.join(", "));
+}
+
+fn report_bound(schedule: &Schedule) {
+    let bound = schedule.lower_bound();
+    let gap = schedule.cost.gap(&bound);
+    if gap.is_zero() {
+        println!("lower bound: {bound} (optimal)");
+    } else {
+        println!("lower bound: {bound} (gap {gap})");
+    }
+}
+
+fn write_schedule(path: &Path, schedule: Schedule) -> Result<()> {
     let mut writer = csv::WriterBuilder::new()
         .has_headers(false)
-        .from_path(&args.output_path)
+        .flexible(true)
+        .from_path(path)
         .unwrap();
     ScheduleModel::from(schedule)
         .serialize_csv(&mut writer)
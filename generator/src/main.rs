@@ -1,5 +1,5 @@
 use {
-    clap::{Parser, arg},
+    clap::Parser,
     std::{
         fs::File,
         io::{Result, Write},
@@ -0,0 +1,179 @@
+use {
+    crate::{
+        Event, Schedule, Weight,
+        constraints::{self, Blame, Members},
+    },
+    std::{fmt, sync::Arc},
+};
+
+// An event by its position in `Schedule::scheme`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub event: usize,
+    pub name: Arc<str>,
+    pub label: String,
+    pub start: String,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, event {}, at {})",
+            self.name,
+            self.label,
+            self.event + 1,
+            self.start
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    // A leader, or a resource shown as `@name`, has more slots of events than its capacity
+    // times the slots it can use.
+    Overloaded {
+        participant: Arc<str>,
+        load: usize,
+        room: usize,
+        events: Vec<Position>,
+    },
+    // Pinned events violate a hard constraint among themselves.
+    Pinned {
+        constraint: String,
+        violations: u64,
+        events: Vec<Position>,
+    },
+    // A hard constraint is violated by every arrangement.
+    Unavoidable {
+        constraint: String,
+        violations: u64,
+    },
+}
+
+fn list(f: &mut fmt::Formatter<'_>, events: &[Position]) -> fmt::Result {
+    for (k, event) in events.iter().enumerate() {
+        write!(f, "{}{event}", if k == 0 { ": " } else { ", " })?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Overloaded {
+                participant,
+                load,
+                room,
+                events,
+            } => {
+                write!(
+                    f,
+                    "{participant} has {load} slots of events but room for at most {room}"
+                )?;
+                list(f, events)
+            }
+            Issue::Pinned {
+                constraint,
+                violations,
+                events,
+            } => {
+                write!(
+                    f,
+                    "pinned events alone give {violations} {constraint} violations"
+                )?;
+                list(f, events)
+            }
+            Issue::Unavoidable {
+                constraint,
+                violations,
+            } => write!(
+                f,
+                "at least {violations} {constraint} violations are unavoidable"
+            ),
+        }
+    }
+}
+
+fn position(schedule: &Schedule, line: usize, event: usize) -> Position {
+    Position {
+        line,
+        event,
+        name: schedule.scheme[line][event].name.clone(),
+        label: schedule.line_label(line),
+        start: schedule.slot_label(schedule.idx[line][event]),
+    }
+}
+
+// Structural reasons why `schedule` cannot reach zero hard cost whatever the optimizer does.
+pub fn analyze(schedule: &Schedule) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let kinds: [(&str, Members); 2] = [("", |e| &e.participants), ("@", |e| &e.resources)];
+    for (prefix, participants) in kinds {
+        for (id, (load, width)) in constraints::load(schedule, participants)
+            .into_iter()
+            .enumerate()
+        {
+            let room = width * schedule.capacity(id);
+            if load > room {
+                let mut events = Vec::new();
+                for (line, line_events) in schedule.scheme.iter().enumerate() {
+                    for (i, event) in line_events.iter().enumerate() {
+                        if participants(event).iter().any(|p| p.id == id) {
+                            events.push(position(schedule, line, i));
+                        }
+                    }
+                }
+                issues.push(Issue::Overloaded {
+                    participant: format!("{prefix}{}", schedule.registry.name(id)).into(),
+                    load,
+                    room,
+                    events,
+                });
+            }
+        }
+    }
+
+    let hard: Vec<usize> = (0..schedule.constraints.len())
+        .filter(|&k| matches!(schedule.weights[k], Weight::Hard(_)))
+        .collect();
+
+    let mut pinned = schedule.clone();
+    for events in &mut pinned.scheme {
+        for event in events.iter_mut().filter(|e| !e.pinned) {
+            *event = Event::new_break("".into(), event.len);
+        }
+    }
+    pinned.update();
+    let mut blame = Blame::new();
+    for &k in &hard {
+        blame.clear();
+        let violations = pinned.constraints[k].evaluate(&pinned, &mut blame);
+        if violations > 0 {
+            blame.sort_unstable();
+            blame.dedup_by_key(|(key, _)| *key);
+            issues.push(Issue::Pinned {
+                constraint: pinned.constraints[k].name().to_string(),
+                violations,
+                events: blame
+                    .iter()
+                    .map(|&((line, i), _)| position(schedule, line, i))
+                    .collect(),
+            });
+        }
+    }
+
+    for &k in &hard {
+        let violations = schedule.constraints[k].lower_bound(schedule);
+        if violations > 0 {
+            issues.push(Issue::Unavoidable {
+                constraint: schedule.constraints[k].name().to_string(),
+                violations,
+            });
+        }
+    }
+
+    issues
+}
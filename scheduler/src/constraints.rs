@@ -0,0 +1,323 @@
+use {
+    crate::{Event, Id, Participant, Schedule},
+    std::{collections::BTreeMap, ops::Range},
+};
+
+pub type Blame = Vec<((usize, usize), usize)>;
+
+// Buffers a constraint may reuse across delta evaluations, so a move does not allocate.
+#[derive(Debug, Clone, Default)]
+pub struct Scratch {
+    // Events per participant id in the current slot, valid while the first field is `round`.
+    pub count: Vec<(u64, usize)>,
+    pub round: u64,
+}
+
+// Which participants of an event a constraint looks at: its leaders or its resources.
+pub(crate) type Members = fn(&Event) -> &[Participant];
+
+pub trait Constraint: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Full evaluation: violations in the whole schedule, pushing `((line, event), amount)`
+    /// into `blame` for every event taking part in one. `Schedule` turns violations into
+    /// `Cost` through the `Weight` the constraint was added with.
+    fn evaluate(&self, schedule: &Schedule, blame: &mut Blame) -> u64;
+
+    /// Delta evaluation: violations and blame restricted to `slots`. Constraints whose count is a
+    /// sum over slots return `Some`, letting `Schedule` re-evaluate only the slots touched by
+    /// a move; the others return `None` and are re-evaluated in full. `scratch` is kept by
+    /// the caller between calls and holds nothing meaningful from one call to the next.
+    fn evaluate_slots(
+        &self,
+        _schedule: &Schedule,
+        _slots: Range<usize>,
+        _blame: &mut Blame,
+        _scratch: &mut Scratch,
+    ) -> Option<u64> {
+        None
+    }
+
+    /// Violations no arrangement of the lines can go below. Summed over constraints this
+    /// bounds the optimum from below; `0` is always valid and is the default.
+    fn lower_bound(&self, _schedule: &Schedule) -> u64 {
+        0
+    }
+}
+
+// Total event length of every participant and the slots it can use: the most its lines may
+// hold, counting how far movable events relocated into a line may push its fixed ones back,
+// or the longest any line may grow to when one of its events is movable.
+pub(crate) fn load(schedule: &Schedule, participants: Members) -> Vec<(usize, usize)> {
+    let reach = schedule.reach();
+    let mut load = vec![(0, 0); schedule.registry.len()];
+    for (line, events) in schedule.scheme.iter().enumerate() {
+        let width = schedule.room(line);
+        for event in events {
+            for participant in participants(event) {
+                let entry = &mut load[participant.id];
+                entry.0 += event.len;
+                entry.1 = entry.1.max(if event.movable { reach } else { width });
+            }
+        }
+    }
+    load
+}
+
+// Every slot where `c` events share a participant of capacity `k < c` (1 unless set in
+// `Schedule::capacities`) costs `(c-k)(c-k+1)/2`, and each of those events is blamed `c-k`.
+// The counts of a slot are stamped with its round instead of being reset, so a slot without
+// shared participants is read only once.
+fn clashes<F>(
+    schedule: &Schedule,
+    slots: Range<usize>,
+    blame: &mut Blame,
+    scratch: &mut Scratch,
+    participants: F,
+) -> u64
+where
+    F: Fn(&Event) -> &[Participant],
+{
+    let Scratch { count, round } = scratch;
+    count.resize(schedule.registry.len(), (0, 0));
+    let present = |slot: usize| {
+        schedule
+            .event
+            .iter()
+            .zip(&schedule.scheme)
+            .enumerate()
+            .filter_map(move |(line, (owners, events))| {
+                let i = *owners.get(slot)?;
+                Some(((line, i), &events[i]))
+            })
+    };
+
+    let mut cost = 0;
+    for slot in slots {
+        *round += 1;
+        let mut shared = false;
+        for (_, event) in present(slot) {
+            for participant in participants(event) {
+                let (stamp, c) = &mut count[participant.id];
+                if *stamp != *round {
+                    *stamp = *round;
+                    *c = 0;
+                }
+                *c += 1;
+                // Capacities are at least 1, so a participant alone needs no lookup.
+                if *c > 1 {
+                    shared = true;
+                    cost += c.saturating_sub(schedule.capacity(participant.id)) as u64;
+                }
+            }
+        }
+        if shared {
+            for (key, event) in present(slot) {
+                for participant in participants(event) {
+                    let (_, c) = count[participant.id];
+                    if c > 1 {
+                        let over = c.saturating_sub(schedule.capacity(participant.id));
+                        if over > 0 {
+                            blame.push((key, over));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    cost
+}
+
+// A participant with `total` slots of events over `width` slots clashes least when its
+// events are spread evenly: `total % width` slots hold one event more than the rest.
+fn clashes_lower_bound(schedule: &Schedule, participants: Members) -> u64 {
+    load(schedule, participants)
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, (_, width))| width > 0)
+        .map(|(id, (total, width))| {
+            let k = schedule.capacity(id);
+            let cost = |c: usize| c.saturating_sub(k) * (c.saturating_sub(k) + 1) / 2;
+            let (q, r) = (total / width, total % width);
+            (r * cost(q + 1) + (width - r) * cost(q)) as u64
+        })
+        .sum()
+}
+
+pub struct LeaderClash;
+
+impl Constraint for LeaderClash {
+    fn name(&self) -> &str {
+        "leader clash"
+    }
+
+    fn evaluate(&self, schedule: &Schedule, blame: &mut Blame) -> u64 {
+        let slots = 0..schedule.width();
+        self.evaluate_slots(schedule, slots, blame, &mut Scratch::default())
+            .unwrap_or_default()
+    }
+
+    fn evaluate_slots(
+        &self,
+        schedule: &Schedule,
+        slots: Range<usize>,
+        blame: &mut Blame,
+        scratch: &mut Scratch,
+    ) -> Option<u64> {
+        Some(clashes(schedule, slots, blame, scratch, |e| {
+            &e.participants
+        }))
+    }
+
+    fn lower_bound(&self, schedule: &Schedule) -> u64 {
+        clashes_lower_bound(schedule, |e| &e.participants)
+    }
+}
+
+// Double-bookings of rooms and other shared resources, counted like leader clashes.
+pub struct ResourceClash;
+
+impl Constraint for ResourceClash {
+    fn name(&self) -> &str {
+        "resource clash"
+    }
+
+    fn evaluate(&self, schedule: &Schedule, blame: &mut Blame) -> u64 {
+        let slots = 0..schedule.width();
+        self.evaluate_slots(schedule, slots, blame, &mut Scratch::default())
+            .unwrap_or_default()
+    }
+
+    fn evaluate_slots(
+        &self,
+        schedule: &Schedule,
+        slots: Range<usize>,
+        blame: &mut Blame,
+        scratch: &mut Scratch,
+    ) -> Option<u64> {
+        Some(clashes(schedule, slots, blame, scratch, |e| &e.resources))
+    }
+
+    fn lower_bound(&self, schedule: &Schedule) -> u64 {
+        clashes_lower_bound(schedule, |e| &e.resources)
+    }
+}
+
+// Blocked slot ranges by leader id.
+pub type Blocked = BTreeMap<Id, Vec<Range<usize>>>;
+
+pub struct Unavailability {
+    pub slots: Blocked,
+}
+
+impl Unavailability {
+    pub fn new(slots: Blocked) -> Self {
+        Self { slots }
+    }
+
+    pub fn is_unavailable(&self, id: Id, slot: usize) -> bool {
+        self.slots
+            .get(&id)
+            .is_some_and(|ranges| ranges.iter().any(|r| r.contains(&slot)))
+    }
+}
+
+impl Constraint for Unavailability {
+    fn name(&self) -> &str {
+        "unavailability"
+    }
+
+    fn evaluate(&self, schedule: &Schedule, blame: &mut Blame) -> u64 {
+        let slots = 0..schedule.width();
+        self.evaluate_slots(schedule, slots, blame, &mut Scratch::default())
+            .unwrap_or_default()
+    }
+
+    fn evaluate_slots(
+        &self,
+        schedule: &Schedule,
+        slots: Range<usize>,
+        blame: &mut Blame,
+        _scratch: &mut Scratch,
+    ) -> Option<u64> {
+        let mut cost = 0;
+
+        for slot in slots {
+            for (line, events) in schedule.event.iter().enumerate() {
+                if let Some(&i) = events.get(slot) {
+                    let blocked = schedule.scheme[line][i]
+                        .participants
+                        .iter()
+                        .filter(|p| self.is_unavailable(p.id, slot))
+                        .count();
+                    if blocked >= 1 {
+                        cost += blocked as u64;
+                        blame.push(((line, i), blocked));
+                    }
+                }
+            }
+        }
+
+        Some(cost)
+    }
+
+    // Within one line a participant's events cannot overlap, so those longer in total than
+    // the available slots the line may hold must spill into blocked ones. Movable events
+    // may leave.
+    fn lower_bound(&self, schedule: &Schedule) -> u64 {
+        let mut bound = 0;
+        for (line, events) in schedule.scheme.iter().enumerate() {
+            let width = schedule.room(line);
+            let mut total: BTreeMap<Id, usize> = BTreeMap::new();
+            for event in events.iter().filter(|e| !e.movable) {
+                for participant in &event.participants {
+                    *total.entry(participant.id).or_default() += event.len;
+                }
+            }
+            for (id, total) in total {
+                let available = (0..width).filter(|&s| !self.is_unavailable(id, s)).count();
+                bound += total.saturating_sub(available) as u64;
+            }
+        }
+        bound
+    }
+}
+
+pub struct LeaderGaps;
+
+impl Constraint for LeaderGaps {
+    fn name(&self) -> &str {
+        "leader gaps"
+    }
+
+    fn evaluate(&self, schedule: &Schedule, blame: &mut Blame) -> u64 {
+        let mut cost = 0;
+        let mut busy: Vec<(Id, usize, usize, usize)> = Vec::new();
+
+        for (line, events) in schedule.scheme.iter().enumerate() {
+            for (i, event) in events.iter().enumerate() {
+                for participant in &event.participants {
+                    busy.push((participant.id, schedule.idx[line][i], line, i));
+                }
+            }
+        }
+        busy.sort_unstable();
+
+        for group in busy.chunk_by(|a, b| a.0 == b.0) {
+            let mut reach = None;
+            for &(_, start, line, i) in group {
+                if let Some(end) = reach
+                    && start > end
+                {
+                    cost += (start - end) as u64;
+                    blame.push(((line, i), start - end));
+                }
+                let end = start + schedule.scheme[line][i].len;
+                reach = Some(reach.map_or(end, |reach: usize| reach.max(end)));
+            }
+        }
+
+        cost
+    }
+}
@@ -0,0 +1,84 @@
+use std::{fmt, iter::Sum, ops::Add};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cost {
+    pub hard: u64,
+    pub soft: u64,
+}
+
+impl Cost {
+    pub const ZERO: Cost = Cost { hard: 0, soft: 0 };
+
+    pub fn new(hard: u64, soft: u64) -> Self {
+        Self { hard, soft }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    pub fn is_feasible(&self) -> bool {
+        self.hard == 0
+    }
+
+    // How far `self` is above `bound`, per component.
+    pub fn gap(&self, bound: &Cost) -> Cost {
+        Cost::new(
+            self.hard.saturating_sub(bound.hard),
+            self.soft.saturating_sub(bound.soft),
+        )
+    }
+
+    // How much worse `self` is than `other` under `objective`, as an annealing energy.
+    pub fn delta(&self, other: &Cost, objective: Objective) -> f64 {
+        let hard = self.hard as f64 - other.hard as f64;
+        let soft = self.soft as f64 - other.soft as f64;
+        match objective {
+            Objective::Lexicographic if hard != 0.0 => hard,
+            Objective::Lexicographic => soft,
+            Objective::Weighted(weight) => hard * weight + soft,
+        }
+    }
+}
+
+impl Add for Cost {
+    type Output = Cost;
+
+    fn add(self, rhs: Cost) -> Cost {
+        Cost::new(self.hard + rhs.hard, self.soft + rhs.soft)
+    }
+}
+
+impl Sum for Cost {
+    fn sum<I: Iterator<Item = Cost>>(iter: I) -> Cost {
+        iter.fold(Cost::ZERO, Add::add)
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} hard, {} soft", self.hard, self.soft)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weight {
+    Hard(u64),
+    Soft(u64),
+}
+
+impl Weight {
+    pub fn apply(&self, violations: u64) -> Cost {
+        match *self {
+            Weight::Hard(weight) => Cost::new(violations * weight, 0),
+            Weight::Soft(weight) => Cost::new(0, violations * weight),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Objective {
+    #[default]
+    Lexicographic,
+    Weighted(f64),
+}
@@ -0,0 +1,300 @@
+use {
+    crate::{
+        Event, Schedule, Weight,
+        constraints::{Blame, Scratch},
+    },
+    std::{
+        ops::{ControlFlow, Range},
+        time::{Duration, Instant},
+    },
+};
+
+#[derive(Debug, Clone)]
+pub enum Verdict {
+    Feasible(Vec<Vec<Event>>),
+    Infeasible,
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub verdict: Verdict,
+    pub nodes: usize,
+}
+
+struct Frame {
+    line: usize,
+    pos: usize,
+    next: usize,
+    tried: Vec<usize>,
+    placed: Option<usize>,
+}
+
+impl Frame {
+    fn new((line, pos): (usize, usize)) -> Self {
+        Self {
+            line,
+            pos,
+            next: 0,
+            tried: Vec::new(),
+            placed: None,
+        }
+    }
+}
+
+fn push(work: &mut Schedule, line: usize, event: Event) {
+    let i = work.scheme[line].len();
+    let start = work.idx[line][i];
+    work.idx[line].push(start + event.len);
+    work.event[line].extend(std::iter::repeat_n(i, event.len));
+    work.scheme[line].push(event);
+}
+
+fn pop(work: &mut Schedule, line: usize) {
+    let event = work.scheme[line].pop().unwrap();
+    work.idx[line].pop();
+    let len = work.event[line].len();
+    work.event[line].truncate(len - event.len);
+}
+
+fn violated(
+    work: &Schedule,
+    hard: &[usize],
+    slots: Range<usize>,
+    blame: &mut Blame,
+    scratch: &mut Scratch,
+) -> bool {
+    hard.iter().any(|&k| {
+        blame.clear();
+        work.constraints[k]
+            .evaluate_slots(work, slots.clone(), blame, scratch)
+            .is_some_and(|violations| violations > 0)
+    })
+}
+
+// Whether the free events of a unit-length line can still be matched to its free slots.
+fn matchable(fits: &[Vec<bool>], events: &[usize], slots: &[usize]) -> bool {
+    fn augment(
+        fits: &[Vec<bool>],
+        slots: &[usize],
+        j: usize,
+        seen: &mut [bool],
+        owner: &mut [Option<usize>],
+    ) -> bool {
+        for (k, &slot) in slots.iter().enumerate() {
+            if fits[j][slot] && !seen[k] {
+                seen[k] = true;
+                if owner[k].is_none_or(|other| augment(fits, slots, other, seen, owner)) {
+                    owner[k] = Some(j);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    let mut owner = vec![None; slots.len()];
+    events.iter().all(|&j| {
+        let mut seen = vec![false; slots.len()];
+        augment(fits, slots, j, &mut seen, &mut owner)
+    })
+}
+
+// Depth-first search over the orderings of every line for an arrangement without hard
+// violations. Events are placed line by line, left to right, and a branch is cut as soon
+// as a slot-local hard constraint reports a violation on the slots of the event just placed,
+// which is sound for constraints that only grow as events are added and do not depend on
+// the line an event is in (all built-in ones). Hard constraints without `evaluate_slots` are
+// checked once a full arrangement is reached. Pinned events keep their position and start
+// slot, movable events stay in their lines, and equal events of a line are tried once per
+// position. In lines of unit-length events every event is probed against every slot once the
+// lines before are fixed, and a branch is also cut when the remaining events can no longer
+// be matched to the remaining slots. Relocations are not searched, so an exhausted search
+// only proves infeasibility when no event is movable and is `Unknown` otherwise.
+pub fn solve<F>(schedule: &Schedule, time_limit: Option<Duration>, mut tick_func: F) -> Solution
+where
+    F: FnMut(usize) -> ControlFlow<()>,
+{
+    let deadline = time_limit.map(|limit| Instant::now() + limit);
+
+    let original = &schedule.scheme;
+    let mut work = schedule.clone();
+    for line in 0..work.scheme.len() {
+        work.scheme[line].clear();
+        work.event[line].clear();
+        work.idx[line].truncate(1);
+    }
+    let probe = work.scheme.len();
+    work.scheme.push(Vec::new());
+    work.event.push(Vec::new());
+    work.idx.push(vec![0]);
+
+    let hard: Vec<usize> = (0..work.constraints.len())
+        .filter(|&k| matches!(work.weights[k], Weight::Hard(_)))
+        .collect();
+
+    let weight = |e: &Event| e.len * e.participants.len().max(1);
+    let mut lines: Vec<usize> = (0..original.len()).collect();
+    lines.sort_by_key(|&l| std::cmp::Reverse(original[l].iter().map(weight).sum::<usize>()));
+    let positions: Vec<(usize, usize)> = lines
+        .iter()
+        .flat_map(|&l| (0..original[l].len()).map(move |pos| (l, pos)))
+        .collect();
+    let order: Vec<Vec<usize>> = original
+        .iter()
+        .map(|events| {
+            let mut order: Vec<usize> = (0..events.len()).filter(|&j| !events[j].pinned).collect();
+            order.sort_by_key(|&j| std::cmp::Reverse(weight(&events[j])));
+            order
+        })
+        .collect();
+    let unit: Vec<bool> = original
+        .iter()
+        .map(|events| events.iter().all(|e| e.len == 1))
+        .collect();
+
+    let mut used: Vec<Vec<bool>> = original.iter().map(|l| vec![false; l.len()]).collect();
+    let mut fits: Vec<Vec<Vec<bool>>> = vec![Vec::new(); original.len()];
+    let mut blame = Blame::new();
+    let mut scratch = Scratch::default();
+    let mut nodes = 0;
+
+    let mut frames: Vec<Frame> = positions
+        .first()
+        .map(|&p| Frame::new(p))
+        .into_iter()
+        .collect();
+    let mut complete = positions.is_empty();
+
+    loop {
+        if complete {
+            complete = false;
+            work.update();
+            if work.cost.is_feasible() {
+                work.scheme.truncate(probe);
+                return Solution {
+                    verdict: Verdict::Feasible(work.scheme),
+                    nodes,
+                };
+            }
+        }
+
+        let Some(frame) = frames.last_mut() else {
+            break;
+        };
+        let (line, pos) = (frame.line, frame.pos);
+
+        if let Some(j) = frame.placed.take() {
+            pop(&mut work, line);
+            used[line][j] = false;
+        }
+
+        if pos == 0 && frame.next == 0 && unit[line] {
+            let events = &original[line];
+            fits[line] = (0..events.len())
+                .map(|j| {
+                    (0..events.len())
+                        .map(|slot| {
+                            if events[j].pinned || events[slot].pinned {
+                                return j == slot;
+                            }
+                            if slot > 0 {
+                                push(&mut work, probe, Event::new_break("".into(), slot));
+                            }
+                            push(&mut work, probe, events[j].clone());
+                            let fits =
+                                !violated(&work, &hard, slot..slot + 1, &mut blame, &mut scratch);
+                            work.scheme[probe].clear();
+                            work.event[probe].clear();
+                            work.idx[probe].truncate(1);
+                            fits
+                        })
+                        .collect()
+                })
+                .collect();
+        }
+
+        let candidate = if original[line][pos].pinned {
+            (frame.next == 0).then_some(pos)
+        } else {
+            let mut candidate = None;
+            while frame.next < order[line].len() {
+                let j = order[line][frame.next];
+                frame.next += 1;
+                if !used[line][j]
+                    && (!unit[line] || fits[line][j][pos])
+                    && !frame
+                        .tried
+                        .iter()
+                        .any(|&t| original[line][t] == original[line][j])
+                {
+                    candidate = Some(j);
+                    break;
+                }
+            }
+            candidate
+        };
+        let Some(j) = candidate else {
+            frames.pop();
+            continue;
+        };
+        frame.next = frame.next.max(1);
+        frame.tried.push(j);
+
+        nodes += 1;
+        if nodes % 4096 == 0
+            && (tick_func(nodes).is_break()
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline))
+        {
+            return Solution {
+                verdict: Verdict::Unknown,
+                nodes,
+            };
+        }
+
+        let start = work.idx[line][pos];
+        let event = &original[line][j];
+        if event.pinned && start != schedule.idx[line][pos] {
+            continue;
+        }
+        let slots = start..start + event.len;
+
+        push(&mut work, line, event.clone());
+        used[line][j] = true;
+        frame.placed = Some(j);
+
+        if violated(&work, &hard, slots, &mut blame, &mut scratch) {
+            continue;
+        }
+
+        if unit[line] {
+            let free: Vec<usize> = order[line]
+                .iter()
+                .copied()
+                .filter(|&j| !used[line][j])
+                .collect();
+            let slots: Vec<usize> = (pos + 1..original[line].len())
+                .filter(|&slot| !original[line][slot].pinned)
+                .collect();
+            if !matchable(&fits[line], &free, &slots) {
+                continue;
+            }
+        }
+
+        if frames.len() == positions.len() {
+            complete = true;
+            continue;
+        }
+        frames.push(Frame::new(positions[frames.len()]));
+    }
+
+    let movable = original.iter().flatten().any(|e| e.movable);
+    Solution {
+        verdict: if movable {
+            Verdict::Unknown
+        } else {
+            Verdict::Infeasible
+        },
+        nodes,
+    }
+}
@@ -1,54 +1,153 @@
+pub mod analysis;
+pub mod constraints;
+pub mod cost;
+pub mod exact;
 pub mod models;
+pub mod moves;
+pub mod parallel;
+pub mod registry;
+pub mod tabu;
+pub mod tempering;
+
+pub use {
+    cost::{Cost, Objective, Weight},
+    rand,
+};
 
 use {
+    constraints::{Blame, Constraint, LeaderClash, ResourceClash, Scratch},
     indexmap::IndexMap,
-    rand::{random_bool, random_range, seq::SliceRandom},
+    moves::{Mix, Move, Relocation},
+    rand::{Rng, RngExt, seq::SliceRandom},
+    registry::Registry,
     std::{
+        cmp::Ordering,
         collections::BTreeMap,
-        hash::{DefaultHasher, Hash, Hasher},
+        hash::{Hash, Hasher},
+        ops::{ControlFlow, Range},
         sync::Arc,
+        time::{Duration, Instant},
     },
 };
 
-type Id = u64;
+pub type Id = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Participant {
+    pub name: Arc<str>,
+    // Index in the `Registry` of the schedule the event belongs to.
+    pub id: Id,
+}
+
+impl Participant {
+    pub fn new(name: Box<str>) -> Self {
+        let name: Arc<str> = Arc::from(name);
+
+        Self { name, id: Id::MAX }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Event {
     pub name: Arc<str>,
-    pub leader_name: Option<Arc<str>>,
-    pub leader_id: Option<Id>,
+    pub participants: Vec<Participant>,
+    pub resources: Vec<Participant>,
     pub len: usize,
+    pub pinned: bool,
+    pub movable: bool,
+    // Holds slots of its line free, e.g. for lunch; it never has leaders or resources.
+    pub is_break: bool,
 }
 
 impl Hash for Event {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.leader_id.hash(state);
+        for participant in &self.participants {
+            participant.name.hash(state);
+        }
+    }
+}
+
+fn unique(names: Vec<Box<str>>) -> Vec<Participant> {
+    let mut unique: Vec<Participant> = Vec::with_capacity(names.len());
+    for participant in names.into_iter().map(Participant::new) {
+        if !unique.iter().any(|p| p.name == participant.name) {
+            unique.push(participant);
+        }
     }
+    unique
 }
 
 impl Event {
-    pub fn new(name: Box<str>, leader_name: Option<Box<str>>, len: usize) -> Self {
+    pub fn new(name: Box<str>, participants: Vec<Box<str>>, len: usize) -> Self {
         let name: Arc<str> = Arc::from(name);
 
-        let leader_name: Option<Arc<str>> = leader_name.map(|name| Arc::from(name));
-
-        let leader_id = leader_name.clone().map(|name| {
-            let mut hasher = DefaultHasher::new();
-            name.hash(&mut hasher);
-            hasher.finish()
-        });
-
         Self {
             name,
-            leader_name,
-            leader_id,
+            participants: unique(participants),
+            resources: Vec::new(),
             len,
+            pinned: false,
+            movable: false,
+            is_break: false,
+        }
+    }
+
+    pub fn new_break(name: Box<str>, len: usize) -> Self {
+        Self {
+            is_break: true,
+            ..Self::new(name, Vec::new(), len)
+        }
+    }
+
+    pub fn with_resources(mut self, resources: Vec<Box<str>>) -> Self {
+        self.resources = unique(resources);
+        self
+    }
+}
+
+// Drops the entries the sorted `a` and `b` have in common from both.
+fn cancel(a: &mut Blame, b: &mut Blame) {
+    let (mut i, mut j) = (0, 0);
+    let (mut a_len, mut b_len) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => {
+                a[a_len] = a[i];
+                a_len += 1;
+                i += 1;
+            }
+            Ordering::Greater => {
+                b[b_len] = b[j];
+                b_len += 1;
+                j += 1;
+            }
+            Ordering::Equal => {
+                i += 1;
+                j += 1;
+            }
         }
     }
+    a.copy_within(i.., a_len);
+    a.truncate(a_len + a.len() - i);
+    b.copy_within(j.., b_len);
+    b.truncate(b_len + b.len() - j);
 }
 
-pub type Cost = u64;
+// Reused by `change_slots`, so a move does not allocate. They keep what the last change did
+// to the blames and violations, so `revert` can undo it without evaluating again.
+#[derive(Clone, Default)]
+struct Buffers {
+    local: Vec<bool>,
+    removed: Blame,
+    added: Blame,
+    violations: Vec<u64>,
+    // The blames replaced by the last change, of constraints evaluated in full.
+    blames: Vec<Blame>,
+    scratch: Scratch,
+    reverting: bool,
+}
 
+#[derive(Clone)]
 pub struct Schedule {
     pub scheme: Vec<Vec<Event>>,
 
@@ -57,231 +156,721 @@ pub struct Schedule {
 
     pub collisions: IndexMap<(usize, usize), usize>,
 
-    pub len: usize,
-
+    pub constraints: Vec<Arc<dyn Constraint>>,
+    pub weights: Vec<Weight>,
+    pub violations: Vec<u64>,
+    blames: Vec<Blame>,
+    buffers: Buffers,
+
+    // Labels of the first slots, e.g. times; slots past the end are shown by number.
+    pub slots: Vec<Arc<str>>,
+    // Names of the lines, e.g. classes or rooms; they stay with their line as events move.
+    pub lines: Vec<Arc<str>>,
+    pub relocation: Relocation,
+    // Events a leader or resource can take part in at once; absent ids take one.
+    pub capacities: BTreeMap<Id, usize>,
+    pub registry: Registry,
+
+    pub objective: Objective,
     pub cost: Cost,
 }
 
 pub const LAMBDA_OPT_DEFAULT: f64 = 0.99;
 pub const AGING_OPT_DEFAULT: usize = 10000;
 
+#[derive(Debug, Clone, Copy)]
+pub struct Outcome {
+    pub final_cost: Cost,
+    pub best_cost: Cost,
+    pub iterations: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub iteration: usize,
+    pub temperature: f64,
+    pub cost: Cost,
+    pub best_cost: Cost,
+    pub accepted: usize,
+    pub rejected: usize,
+}
+
+pub(crate) enum Step {
+    Skipped,
+    Rejected,
+    Accepted { mv: Move, delta: f64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub lambda: f64,
+    pub aging: usize,
+    pub shuffling: bool,
+    pub greedily: bool,
+    pub moves: Mix,
+    pub time_limit: Option<Duration>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            lambda: LAMBDA_OPT_DEFAULT,
+            aging: AGING_OPT_DEFAULT,
+            shuffling: false,
+            greedily: false,
+            moves: Mix::default(),
+            time_limit: None,
+        }
+    }
+}
+
 impl Schedule {
     pub fn new(scheme: Vec<Vec<Event>>) -> Self {
+        let mut constraints: Vec<(Arc<dyn Constraint>, Weight)> =
+            vec![(Arc::new(LeaderClash), Weight::Hard(1))];
+        if scheme.iter().flatten().any(|e| !e.resources.is_empty()) {
+            constraints.push((Arc::new(ResourceClash), Weight::Hard(1)));
+        }
+        Self::with_constraints(scheme, constraints)
+    }
+
+    pub fn with_constraints(
+        mut scheme: Vec<Vec<Event>>,
+        constraints: Vec<(Arc<dyn Constraint>, Weight)>,
+    ) -> Self {
+        let (constraints, weights): (Vec<_>, Vec<_>) = constraints.into_iter().unzip();
+        let mut registry = Registry::default();
+        scheme
+            .iter_mut()
+            .flatten()
+            .for_each(|e| registry.register(e));
         let lens = scheme
             .iter()
             .map(|i| i.iter().map(|e| e.len).sum::<usize>())
             .collect::<Vec<usize>>();
         let idx = scheme.iter().map(|line| vec![0; line.len() + 1]).collect();
+        let capacity = lens.iter().copied().max().unwrap_or(0);
         let mut me = Self {
+            relocation: Relocation {
+                capacity: vec![capacity; scheme.len()],
+                allowed: BTreeMap::new(),
+            },
+            capacities: BTreeMap::new(),
+            registry,
+            slots: Vec::new(),
+            lines: Vec::new(),
             scheme,
-            len: lens.iter().sum(),
-            cost: 0,
+            objective: Objective::default(),
+            cost: Cost::ZERO,
             event: lens.into_iter().map(|len| vec![0; len]).collect(),
             collisions: IndexMap::new(),
+            weights,
+            violations: vec![0; constraints.len()],
+            blames: vec![Blame::new(); constraints.len()],
+            buffers: Buffers::default(),
+            constraints,
             idx,
         };
         me.update();
         me
     }
 
-    pub fn update(&mut self) {
-        let mut counts: Vec<BTreeMap<Id, usize>> = vec![BTreeMap::new(); self.len];
-        self.cost = 0;
+    pub fn add_constraint<C>(&mut self, constraint: C, weight: Weight)
+    where
+        C: Constraint + 'static,
+    {
+        self.constraints.push(Arc::new(constraint));
+        self.weights.push(weight);
+        self.violations.push(0);
+        self.blames.push(Blame::new());
+        self.update();
+    }
 
-        for line in 0..self.scheme.len() {
-            let mut j = 0;
-            for i in 0..self.scheme[line].len() {
-                let event = &self.scheme[line][i];
-                self.idx[line][i + 1] = self.idx[line][i] + event.len;
-                for _ in 0..event.len {
-                    if let Some(id) = event.leader_id {
-                        let prev_count = *counts[j].get(&id).unwrap_or(&0);
-                        self.cost += prev_count as Cost;
-                        counts[j].insert(id, prev_count + 1);
-                    }
+    pub fn capacity(&self, id: Id) -> usize {
+        self.capacities.get(&id).copied().unwrap_or(1)
+    }
 
-                    self.event[line][j] = i;
-                    j += 1;
-                }
-            }
+    pub fn slot_label(&self, slot: usize) -> String {
+        match self.slots.get(slot) {
+            Some(label) => label.to_string(),
+            None => format!("slot {}", slot + 1),
         }
+    }
 
-        self.collisions.clear();
+    pub fn line_label(&self, line: usize) -> String {
+        match self.lines.get(line).filter(|l| !l.is_empty()) {
+            Some(label) => label.to_string(),
+            None => format!("line {}", line + 1),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.event.iter().map(Vec::len).max().unwrap_or(0)
+    }
 
+    pub fn update(&mut self) {
+        // Lines may have changed length since `idx` and `event` were sized, e.g. when an
+        // arrangement saved before relocations is put back into `scheme`.
         for line in 0..self.scheme.len() {
-            let mut j = 0;
-            for i in 0..self.scheme[line].len() {
-                let event = &self.scheme[line][i];
-                for _ in 0..event.len {
-                    if let Some(id) = event.leader_id {
-                        let c = *counts[j].get(&id).unwrap_or(&0);
-                        if c >= 2 {
-                            let prev = *self.collisions.get(&(line, i)).unwrap_or(&0);
-                            self.collisions.insert((line, i), prev + c - 1);
-                        }
-                    }
-                    j += 1;
-                }
-            }
+            let len = self.scheme[line].iter().map(|e| e.len).sum();
+            self.idx[line].resize(self.scheme[line].len() + 1, 0);
+            self.event[line].resize(len, 0);
+            self.reindex(line, 0..self.scheme[line].len());
         }
+
+        self.collisions.clear();
+
+        let constraints = std::mem::take(&mut self.constraints);
+        for (k, constraint) in constraints.iter().enumerate() {
+            let mut blame = Blame::new();
+            self.violations[k] = constraint.evaluate(self, &mut blame);
+            self.add_blame(&blame);
+            self.blames[k] = blame;
+        }
+        self.constraints = constraints;
+
+        self.cost = self.total();
     }
 
-    fn swap(&mut self, line: usize, a: usize, b: usize) {
-        if a == b {
-            return;
+    // No arrangement of the lines costs less than this; reaching it proves the cost optimal.
+    pub fn lower_bound(&self) -> Cost {
+        self.constraints
+            .iter()
+            .zip(&self.weights)
+            .map(|(constraint, weight)| weight.apply(constraint.lower_bound(self)))
+            .sum()
+    }
+
+    fn total(&self) -> Cost {
+        self.weights
+            .iter()
+            .zip(&self.violations)
+            .map(|(weight, &violations)| weight.apply(violations))
+            .sum()
+    }
+
+    fn add_blame(&mut self, blame: &Blame) {
+        for &(key, amount) in blame {
+            *self.collisions.entry(key).or_insert(0) += amount;
         }
-        if self.scheme[line][a].len == self.scheme[line][b].len {
-            let ai = self.idx[line][a];
-            let bi = self.idx[line][b];
+    }
 
-            let mut new_cost: i64 = self.cost as i64;
+    fn remove_blame(&mut self, blame: &Blame) {
+        for &(key, amount) in blame {
+            let prev = self
+                .collisions
+                .get(&key)
+                .unwrap_or_else(|| panic!("{key:?} {amount}"));
+            if *prev > amount {
+                self.collisions.insert(key, prev - amount);
+            } else {
+                self.collisions.swap_remove(&key);
+            }
+        }
+    }
 
-            self.collisions.swap_remove(&(line, a));
-            self.collisions.swap_remove(&(line, b));
+    fn evaluate_slots(
+        &self,
+        constraint: &dyn Constraint,
+        slots: &[Range<usize>],
+        blame: &mut Blame,
+        scratch: &mut Scratch,
+    ) -> Option<u64> {
+        let mut violations = 0;
+        for range in slots {
+            violations += constraint.evaluate_slots(self, range.clone(), blame, scratch)?;
+        }
+        Some(violations)
+    }
 
-            let mut coll_a = 0usize;
-            let mut coll_b = 0usize;
+    fn change_slots<F>(&mut self, slots: &[Range<usize>], change: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        if self.buffers.reverting {
+            change(self);
+            return self.restore();
+        }
 
-            let len = self.scheme[line][a].len;
-            for l in 0..self.scheme.len() {
-                if l == line {
-                    continue;
+        let constraints = std::mem::take(&mut self.constraints);
+        let mut buffers = std::mem::take(&mut self.buffers);
+        let Buffers {
+            local,
+            removed,
+            added,
+            violations,
+            blames,
+            scratch,
+            ..
+        } = &mut buffers;
+        local.clear();
+        removed.clear();
+        added.clear();
+        violations.clone_from(&self.violations);
+        blames.resize_with(constraints.len(), Blame::new);
+
+        for (k, constraint) in constraints.iter().enumerate() {
+            let start = removed.len();
+            match self.evaluate_slots(&**constraint, slots, removed, scratch) {
+                Some(violations) => {
+                    self.blames[k].clear();
+                    self.violations[k] -= violations;
+                    local.push(true);
                 }
-
-                for i in ai..ai + len {
-                    if i >= self.event[l].len() {
-                        break;
-                    }
-                    let index = self.event[l][i];
-                    let event = &self.scheme[l][index];
-                    if event.leader_id.is_none() {
-                        continue;
-                    }
-                    if event.leader_id == self.scheme[line][b].leader_id {
-                        let prev = self.collisions.get(&(l, index)).unwrap_or(&0);
-                        self.collisions.insert((l, index), prev + 1);
-                        new_cost += 1;
-                        coll_a += 1;
-                    }
-                    if event.leader_id == self.scheme[line][a].leader_id {
-                        let prev = self
-                            .collisions
-                            .get(&(l, index))
-                            .unwrap_or_else(|| panic!("{line} {a} {b} {index} {l}"));
-                        if *prev > 1 {
-                            self.collisions.insert((l, index), prev - 1);
-                        } else {
-                            self.collisions.swap_remove(&(l, index));
-                        }
-                        new_cost -= 1;
-                    }
+                None => {
+                    removed.truncate(start);
+                    removed.extend_from_slice(&self.blames[k]);
+                    local.push(false);
                 }
+            }
+        }
 
-                for i in bi..bi + len {
-                    if i >= self.event[l].len() {
-                        break;
-                    }
-                    let index = self.event[l][i];
-                    let event = &self.scheme[l][index];
-                    if event.leader_id.is_none() {
-                        continue;
-                    }
-                    if event.leader_id == self.scheme[line][a].leader_id {
-                        let prev = self.collisions.get(&(l, index)).unwrap_or(&0);
-                        self.collisions.insert((l, index), prev + 1);
-                        new_cost += 1;
-                        coll_b += 1;
-                    }
-                    if event.leader_id == self.scheme[line][b].leader_id {
-                        // dbg!((l, index));
-                        let prev = self
-                            .collisions
-                            .get(&(l, index))
-                            .unwrap_or_else(|| panic!("{line} {a} {b} {index} {l} {i}"));
-
-                        if *prev > 1 {
-                            self.collisions.insert((l, index), prev - 1);
-                        } else {
-                            self.collisions.swap_remove(&(l, index));
-                        }
-                        new_cost -= 1;
-                    }
-                }
+        change(self);
+
+        for (k, constraint) in constraints.iter().enumerate() {
+            if local[k] {
+                self.violations[k] += self
+                    .evaluate_slots(&**constraint, slots, added, scratch)
+                    .unwrap_or_default();
+            } else {
+                let start = added.len();
+                self.violations[k] = constraint.evaluate(self, added);
+                blames[k].clear();
+                blames[k].extend_from_slice(&added[start..]);
+                std::mem::swap(&mut self.blames[k], &mut blames[k]);
             }
+        }
+        // Most blames in the slots are the same after the change and need not be touched.
+        removed.sort_unstable();
+        added.sort_unstable();
+        cancel(removed, added);
+        self.remove_blame(removed);
+        self.add_blame(added);
+
+        self.constraints = constraints;
+        self.buffers = buffers;
+        self.cost = self.total();
+    }
 
-            if coll_a >= 1 {
-                self.collisions.insert((line, a), coll_a);
+    // Puts back the blames and violations from before the last `change_slots`.
+    fn restore(&mut self) {
+        let mut buffers = std::mem::take(&mut self.buffers);
+        self.remove_blame(&buffers.added);
+        self.add_blame(&buffers.removed);
+        std::mem::swap(&mut self.violations, &mut buffers.violations);
+        for (k, &local) in buffers.local.iter().enumerate() {
+            if !local {
+                std::mem::swap(&mut self.blames[k], &mut buffers.blames[k]);
             }
+        }
+        self.buffers = buffers;
+        self.cost = self.total();
+    }
+
+    pub fn can_swap(&self, line: usize, a: usize, b: usize) -> bool {
+        let events = &self.scheme[line];
+        if events[a].pinned || events[b].pinned {
+            return false;
+        }
+        events[a].len == events[b].len || !events[a.min(b)..a.max(b)].iter().any(|e| e.pinned)
+    }
 
-            if coll_b >= 1 {
-                self.collisions.insert((line, b), coll_b);
+    pub(crate) fn shuffle<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        for line in &mut self.scheme {
+            for segment in line.split_mut(|e| e.pinned) {
+                segment.shuffle(rng);
             }
+        }
+    }
+
+    // Rewrites the offsets and the slot owners of `events`, whose preceding offset is right.
+    fn reindex(&mut self, line: usize, events: Range<usize>) {
+        for i in events {
+            let start = self.idx[line][i];
+            let len = self.scheme[line][i].len;
+            self.idx[line][i + 1] = start + len;
+            self.event[line][start..start + len].fill(i);
+        }
+    }
 
-            self.scheme[line].swap(a, b);
-            self.cost = new_cost as Cost;
+    fn swap(&mut self, line: usize, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        if self.scheme[line][a].len == self.scheme[line][b].len {
+            let ai = self.idx[line][a];
+            let bi = self.idx[line][b];
+            let len = self.scheme[line][a].len;
+
+            self.change_slots(&[ai..ai + len, bi..bi + len], |s| s.scheme[line].swap(a, b));
         } else {
-            self.scheme[line].swap(a, b);
-            self.update();
+            let (a, b) = (a.min(b), a.max(b));
+            let slots = self.idx[line][a]..self.idx[line][b + 1];
+            self.change_slots(&[slots], |s| {
+                s.scheme[line].swap(a, b);
+                s.reindex(line, a..b + 1);
+            });
         }
     }
 
-    pub fn optimize<F>(
+    // One Metropolis step: proposes a move and keeps it with the annealing
+    // acceptance probability at `temperature`.
+    pub(crate) fn step<R>(
         &mut self,
-        opt_lambda: f64,
-        opt_aging: usize,
-        shuffling: bool,
+        rng: &mut R,
+        temperature: f64,
         greedily: bool,
-        mut tick_func: F,
-    ) where
-        F: FnMut(),
+        moves: &Mix,
+    ) -> Step
+    where
+        R: Rng + ?Sized,
     {
+        let Some(mv) = self.propose(rng, greedily, moves) else {
+            return Step::Skipped;
+        };
+        if !self.can_apply(mv) {
+            return Step::Skipped;
+        }
+        let prev_cost = self.cost;
+        self.apply(mv);
+        let delta = self.cost.delta(&prev_cost, self.objective);
+        if delta > 0.0 && !rng.random_bool(f64::exp(-delta / temperature)) {
+            self.revert(mv);
+            Step::Rejected
+        } else {
+            Step::Accepted { mv, delta }
+        }
+    }
+
+    pub fn optimize<R, F>(&mut self, rng: &mut R, options: &Options, mut tick_func: F) -> Outcome
+    where
+        R: Rng + ?Sized,
+        F: FnMut(&Progress) -> ControlFlow<()>,
+    {
+        let deadline = options.time_limit.map(|limit| Instant::now() + limit);
+
         self.update();
         let n = self.scheme.len();
-        if n == 0 || self.cost == 0 {
-            return;
+        if n == 0 || self.cost.is_zero() {
+            return Outcome {
+                final_cost: self.cost,
+                best_cost: self.cost,
+                iterations: 0,
+            };
         }
 
-        if shuffling {
-            for i in &mut self.scheme {
-                i.shuffle(&mut rand::rng());
-            }
+        if options.shuffling {
+            self.shuffle(rng);
             self.update();
         }
 
-        let mut t = 1f64;
-
-        for _ in 0..opt_aging {
-            t *= opt_lambda;
+        // The best arrangement is only copied when the chain is about to leave it,
+        // so improving runs don't pay for a clone per step.
+        let mut best_cost = self.cost;
+        let mut best: Option<Vec<Vec<Event>>> = None;
+        let mut at_best = true;
+
+        let mut progress = Progress {
+            iteration: 0,
+            temperature: 1.0,
+            cost: self.cost,
+            best_cost,
+            accepted: 0,
+            rejected: 0,
+        };
 
-            let (i, a, b) = if greedily {
-                let (i, a) = *self
-                    .collisions
-                    .get_index(random_range(0..self.collisions.len()))
-                    .unwrap()
-                    .0;
-                (i, a, random_range(0..self.scheme[i].len()))
-            } else {
-                let i = random_range(0..self.scheme.len());
-                (
-                    i,
-                    random_range(0..self.scheme[i].len()),
-                    random_range(0..self.scheme[i].len()),
-                )
-            };
-            let prev_cost = self.cost;
-            self.swap(i, a, b);
-            let new_cost = self.cost;
-            if prev_cost < new_cost
-                && !random_bool(f64::exp((prev_cost as i64 - new_cost as i64) as f64 / t))
-            {
-                self.swap(i, a, b);
+        for _ in 0..options.aging {
+            progress.iteration += 1;
+            progress.temperature *= options.lambda;
+
+            match self.step(rng, progress.temperature, options.greedily, &options.moves) {
+                Step::Skipped => {}
+                Step::Rejected => progress.rejected += 1,
+                Step::Accepted { mv, delta } => {
+                    progress.accepted += 1;
+                    if delta > 0.0 && at_best {
+                        self.apply(mv.inverse());
+                        best = Some(self.scheme.clone());
+                        self.apply(mv);
+                        at_best = false;
+                    } else if self.cost.delta(&best_cost, self.objective) < 0.0 {
+                        best_cost = self.cost;
+                        at_best = true;
+                    }
+                }
             }
-            tick_func();
-            if self.cost == 0 {
+            progress.cost = self.cost;
+            progress.best_cost = best_cost;
+            if tick_func(&progress).is_break()
+                || self.cost.is_zero()
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
                 break;
             }
         }
 
+        let final_cost = self.cost;
+        if !at_best && let Some(best) = best {
+            self.scheme = best;
+        }
         self.update();
+
+        Outcome {
+            final_cost,
+            best_cost: self.cost,
+            iterations: progress.iteration,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        constraints::{LeaderGaps, Unavailability},
+        models::{AliasModel, Normalizer, ScheduleModel, csv},
+        rand::{SeedableRng, rngs::StdRng},
+    };
+
+    fn schedule(input: &str) -> Schedule {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        Schedule::from(ScheduleModel::deserialize_csv(&mut reader, false).unwrap())
+    }
+
+    fn write(schedule: Schedule) -> String {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_writer(Vec::new());
+        ScheduleModel::from(schedule)
+            .serialize_csv(&mut writer)
+            .unwrap();
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    // `schedule`, incrementally updated, agrees with a full recomputation.
+    fn assert_consistent(schedule: &Schedule) {
+        let mut fresh = schedule.clone();
+        fresh.update();
+        assert_eq!(schedule.idx, fresh.idx);
+        assert_eq!(schedule.event, fresh.event);
+        assert_eq!(schedule.cost, fresh.cost);
+        assert_eq!(schedule.violations, fresh.violations);
+        let mut collisions: Vec<_> = schedule.collisions.clone().into_iter().collect();
+        let mut expected: Vec<_> = fresh.collisions.into_iter().collect();
+        collisions.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(collisions, expected);
+    }
+
+    const RELOCATABLE: &str = "a:x,b:y,*m:x,*n:y\nc:x,d:y\ne:x,f:y,g:z\n";
+
+    #[test]
+    fn anneal_restores_best_after_relocations() {
+        for seed in 1..=8 {
+            let mut schedule = schedule(RELOCATABLE);
+            let options = Options {
+                aging: 300,
+                moves: Mix {
+                    relocate: 1.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut rng = StdRng::seed_from_u64(seed);
+            schedule.optimize(&mut rng, &options, |_| ControlFlow::Continue(()));
+            assert_consistent(&schedule);
+        }
+    }
+
+    #[test]
+    fn tempering_restores_best_after_relocations() {
+        for seed in 1..=8 {
+            let mut schedule = schedule(RELOCATABLE);
+            let options = tempering::Options {
+                steps: 300,
+                moves: Mix {
+                    relocate: 1.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut rng = StdRng::seed_from_u64(seed);
+            tempering::optimize(&mut schedule, &mut rng, &options, |_| {
+                ControlFlow::Continue(())
+            });
+            assert_consistent(&schedule);
+        }
+    }
+
+    #[test]
+    fn empty_line_round_trips() {
+        let mut emptied = schedule("*a:x,*b:y\nc:z\n");
+        emptied.apply(Move::Relocate {
+            from: 0,
+            i: 0,
+            to: 1,
+            j: 1,
+        });
+        emptied.apply(Move::Relocate {
+            from: 0,
+            i: 0,
+            to: 1,
+            j: 2,
+        });
+        let output = write(emptied);
+        let reread = schedule(&output);
+        assert!(reread.scheme[0].is_empty());
+        assert_eq!(write(reread), output);
+    }
+
+    #[test]
+    fn slot_labels_stay_over_their_slots() {
+        let input = "9:00,10:00,11:00\na:x[2],b:y\n";
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let schedule = Schedule::from(ScheduleModel::deserialize_csv(&mut reader, false).unwrap());
+        assert_eq!(schedule.slot_label(schedule.idx[0][1]), "11:00");
+        assert_eq!(write(schedule), "9:00,10:00,11:00\na:x[2],,b:y\n");
+    }
+
+    fn solve(input: &str) -> exact::Verdict {
+        exact::solve(&schedule(input), None, |_| ControlFlow::Continue(())).verdict
+    }
+
+    // `input` is solved by the exact solver with an arrangement without hard violations.
+    fn assert_solved(input: &str) -> Schedule {
+        let exact::Verdict::Feasible(scheme) = solve(input) else {
+            panic!("no arrangement found for {input:?}");
+        };
+        let mut solved = schedule(input);
+        solved.scheme = scheme;
+        solved.update();
+        assert!(solved.cost.is_feasible());
+        solved
+    }
+
+    #[test]
+    fn exact_finds_feasible_arrangement() {
+        assert_solved("a:x,b:y\nc:x,d:y\n");
+    }
+
+    #[test]
+    fn exact_proves_infeasibility() {
+        assert!(matches!(
+            solve("a:x,b:x\nc:x,d:y\n"),
+            exact::Verdict::Infeasible
+        ));
+    }
+
+    #[test]
+    fn exact_keeps_pinned_events_in_mixed_length_lines() {
+        let solved = assert_solved("q:z,!p:x[2],u:y\nr:y[2],s:x,v:w\n");
+        assert_eq!(&*solved.scheme[0][1].name, "p");
+        assert_eq!(solved.idx[0][1], 1);
+        assert_eq!(solved.scheme[1].len(), 3);
+    }
+
+    #[test]
+    fn exact_is_unknown_with_movable_events() {
+        assert!(matches!(
+            solve("*a:x,b:y\nc:x,d:x\n"),
+            exact::Verdict::Unknown
+        ));
+    }
+
+    #[test]
+    fn normalizer_merges_spellings() {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader("Ann Smith,A. Smith\n".as_bytes());
+        let aliases = AliasModel::deserialize_csv(&mut reader).unwrap();
+        let mut normalizer = Normalizer::new("case,whitespace".parse().unwrap(), Some(aliases));
+        assert_eq!(&*normalizer.canonical("Bob  Jones"), "Bob Jones");
+        assert_eq!(&*normalizer.canonical(" bob jones"), "Bob Jones");
+        assert_eq!(&*normalizer.canonical("a. SMITH"), "Ann Smith");
+        assert_eq!(&*normalizer.canonical("Ann Smith"), "Ann Smith");
+        let merged: Vec<(&str, Vec<&str>)> = normalizer
+            .merged()
+            .iter()
+            .map(|(name, variants)| (&**name, variants.iter().map(|v| &**v).collect()))
+            .collect();
+        assert_eq!(
+            merged,
+            [
+                ("Ann Smith", vec!["a. SMITH"]),
+                ("Bob Jones", vec!["Bob  Jones", "bob jones"]),
+            ]
+        );
+    }
+
+    fn random_scheme(rng: &mut StdRng) -> Vec<Vec<Event>> {
+        let event = |rng: &mut StdRng, k: usize| {
+            let leaders = (0..rng.random_range(0..3))
+                .map(|_| format!("L{}", rng.random_range(0..4)).into())
+                .collect();
+            let mut event = Event::new(format!("e{k}").into(), leaders, rng.random_range(1..4));
+            if rng.random_bool(0.5) {
+                let resource = format!("R{}", rng.random_range(0..2)).into();
+                event = event.with_resources(vec![resource]);
+            }
+            event.movable = rng.random_bool(0.3);
+            event.pinned = rng.random_bool(0.1);
+            event
+        };
+        (0..5)
+            .map(|_| (0..6).map(|k| event(rng, k)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn incremental_updates_match_full() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mix = Mix {
+            swap: 1.0,
+            insert: 1.0,
+            rotate: 1.0,
+            block_swap: 1.0,
+            relocate: 2.0,
+        };
+        let mut relocations = 0;
+        for _ in 0..50 {
+            let mut schedule = Schedule::new(random_scheme(&mut rng));
+            let mut blocked = BTreeMap::new();
+            if let Some(id) = schedule.registry.get("L0") {
+                let start = rng.random_range(0..8);
+                let slots = start..start + rng.random_range(1..4);
+                blocked.insert(id, vec![slots]);
+            }
+            schedule.add_constraint(Unavailability::new(blocked), Weight::Hard(1));
+            schedule.add_constraint(LeaderGaps, Weight::Soft(2));
+            if let Some(id) = schedule.registry.get("L1") {
+                schedule.capacities.insert(id, 2);
+            }
+            schedule.update();
+
+            for _ in 0..200 {
+                if let Some(mv) = schedule.propose(&mut rng, false, &mix)
+                    && schedule.can_apply(mv)
+                {
+                    relocations += matches!(mv, Move::Relocate { .. }) as usize;
+                    let cost = schedule.cost;
+                    schedule.apply(mv);
+                    assert_consistent(&schedule);
+                    if schedule.cost != cost && rng.random_bool(0.5) {
+                        schedule.revert(mv);
+                        assert_consistent(&schedule);
+                        assert_eq!(schedule.cost, cost);
+                    }
+                }
+            }
+        }
+        assert!(relocations > 100);
     }
 }
@@ -4,30 +4,53 @@ pub use {
 };
 
 use {
-    crate::{Event, Schedule},
-    std::str::FromStr,
+    crate::{Event, Id, Schedule, constraints::Blocked, moves::Allowed, registry::Registry},
+    icu_normalizer::ComposingNormalizerBorrowed,
+    std::{
+        collections::{BTreeMap, BTreeSet, HashMap},
+        fmt,
+        ops::Range,
+        str::FromStr,
+        sync::Arc,
+    },
 };
 
 #[derive(Debug)]
 pub struct EventModel {
     pub name: Box<str>,
-    pub leader: Option<Box<str>>,
+    pub leaders: Vec<Box<str>>,
+    pub resources: Vec<Box<str>>,
 
     // #[serde(default = "EventModel::default_len")]
     pub len: usize,
+
+    pub pinned: bool,
+    pub movable: bool,
+    pub is_break: bool,
 }
 
-impl ToString for EventModel {
-    fn to_string(&self) -> String {
-        let base = match &self.leader {
-            Some(leader) => format!("{}:{}", self.name, leader),
-            None => self.name.to_string(),
-        };
+impl fmt::Display for EventModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pinned {
+            write!(f, "!")?;
+        }
+        if self.movable {
+            write!(f, "*")?;
+        }
+        if self.is_break {
+            write!(f, "~")?;
+        }
+        write!(f, "{}", self.name)?;
+        if !self.leaders.is_empty() {
+            write!(f, ":{}", self.leaders.join("+"))?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "@{}", self.resources.join("+"))?;
+        }
         if self.len != 1 {
-            format!("{}[{}]", base, self.len)
-        } else {
-            base
+            write!(f, "[{}]", self.len)?;
         }
+        Ok(())
     }
 }
 
@@ -35,13 +58,28 @@ impl FromStr for EventModel {
     type Err = std::io::Error;
 
     fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (pinned, s) = match s.strip_prefix('!') {
+            Some(s) => (true, s.trim_start()),
+            None => (false, s),
+        };
+        let (movable, s) = match s.strip_prefix('*') {
+            Some(s) => (true, s.trim_start()),
+            None => (false, s),
+        };
+        let (is_break, s) = match s.strip_prefix('~') {
+            Some(s) => (true, s.trim_start()),
+            None => (false, s),
+        };
         let mut s = String::from(s);
         let mut len = 1;
-        if s.chars().last().ok_or(Error::new(
-            ErrorKind::InvalidInput,
-            "missing field: \"event\"",
-        ))? == ']'
-        {
+        if !is_break && s.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "missing field: \"event\"",
+            ));
+        }
+        if s.ends_with(']') {
             let (other, len_s) =
                 s.get(0..s.len() - 1)
                     .unwrap()
@@ -60,27 +98,83 @@ impl FromStr for EventModel {
             s = other.trim().to_string();
         }
 
-        let event = if let Some((name, leader)) = s.split_once(':') {
-            let name = name.trim();
-            let leader = leader.trim();
+        // `~name[len]`, or just `~`: a break, whose name is optional.
+        if is_break {
+            if s.contains([':', '@']) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "a break has no leaders or resources",
+                ));
+            }
+            return Ok(EventModel {
+                name: Box::from(s),
+                leaders: Vec::new(),
+                resources: Vec::new(),
+                len,
+                pinned,
+                movable,
+                is_break,
+            });
+        }
 
-            if name.is_empty() {
+        let mut resources = Vec::new();
+        if let Some((other, resources_s)) = s.split_once('@') {
+            if resources_s.contains(':') {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
-                    "missing field: \"name\"",
+                    ErrorKind::InvalidData,
+                    "leaders go before resources: name:leader@resource",
                 ));
             }
-            if leader.is_empty() {
+            resources = resources_s
+                .split('+')
+                .map(|resource| {
+                    let resource = resource.trim();
+                    if resource.is_empty() {
+                        Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            "missing field: \"resource\"",
+                        ))
+                    } else {
+                        Ok(Box::from(resource))
+                    }
+                })
+                .collect::<Result<Vec<Box<str>>>>()?;
+            s = other.trim().to_string();
+        }
+
+        let event = if let Some((name, leaders)) = s.split_once(':') {
+            let name = name.trim();
+
+            if name.is_empty() {
                 return Err(Error::new(
                     ErrorKind::InvalidInput,
-                    "missing field: \"leader\"",
+                    "missing field: \"name\"",
                 ));
             }
 
+            let leaders = leaders
+                .split('+')
+                .map(|leader| {
+                    let leader = leader.trim();
+                    if leader.is_empty() {
+                        Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            "missing field: \"leader\"",
+                        ))
+                    } else {
+                        Ok(Box::from(leader))
+                    }
+                })
+                .collect::<Result<Vec<Box<str>>>>()?;
+
             EventModel {
                 name: Box::from(name),
-                leader: Some(Box::from(leader)),
+                leaders,
+                resources,
                 len,
+                pinned,
+                movable,
+                is_break,
             }
         } else {
             if s.is_empty() {
@@ -91,8 +185,12 @@ impl FromStr for EventModel {
             }
             EventModel {
                 name: Box::from(s),
-                leader: None,
+                leaders: Vec::new(),
+                resources,
                 len,
+                pinned,
+                movable,
+                is_break,
             }
         };
 
@@ -100,9 +198,15 @@ impl FromStr for EventModel {
     }
 }
 
-impl Into<Event> for EventModel {
-    fn into(self) -> Event {
-        Event::new(self.name, self.leader, self.len)
+impl From<EventModel> for Event {
+    fn from(e: EventModel) -> Self {
+        let mut event = match e.is_break {
+            true => Event::new_break(e.name, e.len),
+            false => Event::new(e.name, e.leaders, e.len).with_resources(e.resources),
+        };
+        event.pinned = e.pinned;
+        event.movable = e.movable;
+        event
     }
 }
 
@@ -110,53 +214,114 @@ impl From<Event> for EventModel {
     fn from(e: Event) -> Self {
         EventModel {
             name: Box::from(e.name.as_ref()),
-            leader: e.leader_name.map(|l| Box::from(l.as_ref())),
+            leaders: e
+                .participants
+                .into_iter()
+                .map(|p| Box::from(p.name.as_ref()))
+                .collect(),
+            resources: e
+                .resources
+                .into_iter()
+                .map(|p| Box::from(p.name.as_ref()))
+                .collect(),
             len: e.len,
+            pinned: e.pinned,
+            movable: e.movable,
+            is_break: e.is_break,
         }
     }
 }
 
 #[derive(Debug)]
-pub struct ScheduleModel(Vec<Vec<EventModel>>);
+pub struct ScheduleModel {
+    scheme: Vec<Vec<EventModel>>,
+    // One label per slot, read from and written to a header row. Lines are then written one
+    // cell per slot, with blank cells for the slots after the first of a longer event.
+    pub slots: Vec<Box<str>>,
+    // One name per line, read from and written to a leading column.
+    pub lines: Vec<Box<str>>,
+}
 
-impl Into<Vec<Vec<Event>>> for ScheduleModel {
-    fn into(self) -> Vec<Vec<Event>> {
-        self.0
+impl From<ScheduleModel> for Vec<Vec<Event>> {
+    fn from(s: ScheduleModel) -> Self {
+        s.scheme
             .into_iter()
             .map(|i| i.into_iter().map(|e| e.into()).collect())
             .collect()
     }
 }
 
+impl From<ScheduleModel> for Schedule {
+    fn from(s: ScheduleModel) -> Self {
+        let slots = s.slots.iter().map(|l| Arc::from(l.as_ref())).collect();
+        let lines = s.lines.iter().map(|l| Arc::from(l.as_ref())).collect();
+        let mut schedule = Schedule::new(s.into());
+        schedule.slots = slots;
+        schedule.lines = lines;
+        schedule
+    }
+}
+
 impl From<Vec<Vec<Event>>> for ScheduleModel {
     fn from(e: Vec<Vec<Event>>) -> Self {
-        ScheduleModel(
-            e.into_iter()
+        ScheduleModel {
+            scheme: e
+                .into_iter()
                 .map(|i| i.into_iter().map(|e| e.into()).collect())
                 .collect(),
-        )
+            slots: Vec::new(),
+            lines: Vec::new(),
+        }
     }
 }
 
 impl From<Schedule> for ScheduleModel {
     fn from(s: Schedule) -> Self {
-        ScheduleModel(
-            s.scheme
+        ScheduleModel {
+            scheme: s
+                .scheme
                 .into_iter()
                 .map(|i| i.into_iter().map(|e| e.into()).collect())
                 .collect(),
-        )
+            slots: s.slots.iter().map(|l| Box::from(l.as_ref())).collect(),
+            lines: s.lines.iter().map(|l| Box::from(l.as_ref())).collect(),
+        }
     }
 }
 
 impl ScheduleModel {
+    pub fn normalize(&mut self, normalizer: &mut Normalizer) {
+        for event in self.scheme.iter_mut().flatten() {
+            for name in event.leaders.iter_mut().chain(&mut event.resources) {
+                *name = normalizer.canonical(name);
+            }
+        }
+    }
+
     pub fn serialize_csv<W>(self, writer: &mut csv::Writer<W>) -> csv::Result<()>
     where
         W: std::io::Write,
     {
-        for line in self.0 {
+        let labelled = !self.lines.is_empty();
+        if !self.slots.is_empty() {
+            let corner = labelled.then_some("".as_bytes());
+            writer.write_record(
+                corner
+                    .into_iter()
+                    .chain(self.slots.iter().map(|l| l.as_bytes())),
+            )?;
+        }
+        for (k, line) in self.scheme.into_iter().enumerate() {
+            if labelled {
+                writer.write_field(self.lines.get(k).map_or("", |l| l.as_ref()))?;
+            }
             for event in line {
                 writer.write_field(event.to_string())?;
+                if !self.slots.is_empty() {
+                    for _ in 1..event.len {
+                        writer.write_field("")?;
+                    }
+                }
             }
             writer.write_record(None::<&[u8]>)?;
         }
@@ -164,19 +329,365 @@ impl ScheduleModel {
         Ok(())
     }
 
-    pub fn deserialize_csv<R>(reader: &mut csv::Reader<R>) -> io::Result<Self>
+    // Slot labels are taken from the header row when `reader` is set to have one, and line
+    // names from the first column of every row when `labelled`.
+    pub fn deserialize_csv<R>(reader: &mut csv::Reader<R>, labelled: bool) -> io::Result<Self>
     where
         R: io::Read,
     {
+        let slots = match reader.has_headers() {
+            true => reader
+                .headers()?
+                .iter()
+                .skip(labelled as usize)
+                .map(|l| l.trim().into())
+                .collect(),
+            false => Vec::new(),
+        };
+        let mut lines = Vec::new();
         let scheme: Vec<Vec<EventModel>> = reader
             .deserialize()
             .map(|line| {
-                let line: Vec<String> = line.unwrap();
+                let mut line: Vec<String> = line.unwrap();
+                if labelled && !line.is_empty() {
+                    lines.push(line.remove(0).trim().into());
+                } else if labelled {
+                    lines.push("".into());
+                }
+                // Blank cells hold no event, e.g. the one written for a line left empty.
                 line.into_iter()
+                    .filter(|e| !e.trim().is_empty())
                     .map(|e| EventModel::from_str(&e))
                     .collect::<Result<Vec<EventModel>>>()
             })
             .collect::<Result<Vec<Vec<EventModel>>>>()?;
-        Ok(Self(scheme))
+        Ok(Self {
+            scheme,
+            slots,
+            lines,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct AvailabilityModel(Vec<(Box<str>, Vec<Range<usize>>)>);
+
+impl AvailabilityModel {
+    pub fn normalize(&mut self, normalizer: &mut Normalizer) {
+        for (leader, _) in &mut self.0 {
+            *leader = normalizer.canonical(leader);
+        }
+    }
+
+    // Blocked slots by the id of each leader in `registry`, and the leaders not in it.
+    pub fn resolve(self, registry: &Registry) -> (Blocked, Vec<Box<str>>) {
+        let mut unavailable = Blocked::new();
+        let mut unknown = Vec::new();
+        for (leader, ranges) in self.0 {
+            match registry.get(&leader) {
+                Some(id) => unavailable.entry(id).or_default().extend(ranges),
+                None => unknown.push(leader),
+            }
+        }
+        (unavailable, unknown)
+    }
+
+    // Slots are numbered from 1, as in reports: `3` or `2-4`.
+    fn parse_range(s: &str) -> Result<Range<usize>> {
+        let parse = |s: &str| match s.trim().parse::<usize>() {
+            Ok(0) => Err(Error::new(
+                ErrorKind::InvalidData,
+                "field \"slot\" is numbered from 1",
+            )),
+            Ok(slot) => Ok(slot - 1),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                "field \"slot\" expected type: <integer>",
+            )),
+        };
+        let (from, to) = match s.split_once('-') {
+            Some((from, to)) => (parse(from)?, parse(to)?),
+            None => {
+                let slot = parse(s)?;
+                (slot, slot)
+            }
+        };
+        if from > to {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("empty slot range: \"{}\"", s.trim()),
+            ));
+        }
+        Ok(from..to + 1)
+    }
+
+    pub fn deserialize_csv<R>(reader: &mut csv::Reader<R>) -> io::Result<Self>
+    where
+        R: io::Read,
+    {
+        let mut leaders = Vec::new();
+        for line in reader.records() {
+            let line = line?;
+            let mut fields = line.iter().map(str::trim).filter(|f| !f.is_empty());
+            let Some(leader) = fields.next() else {
+                continue;
+            };
+            let ranges = fields
+                .map(Self::parse_range)
+                .collect::<Result<Vec<Range<usize>>>>()?;
+            leaders.push((Box::from(leader), ranges));
+        }
+        Ok(Self(leaders))
+    }
+}
+
+#[derive(Debug)]
+pub struct CompatibilityModel(Vec<(Box<str>, Vec<usize>)>);
+
+impl CompatibilityModel {
+    // Allowed lines by event name, and the names of no event in `scheme`; lines past its
+    // end are an error.
+    pub fn resolve(self, scheme: &[Vec<Event>]) -> Result<(Allowed, Vec<Box<str>>)> {
+        let names: BTreeSet<&str> = scheme.iter().flatten().map(|e| &*e.name).collect();
+        let mut allowed = Allowed::new();
+        let mut unknown = Vec::new();
+        for (event, lines) in self.0 {
+            if let Some(&line) = lines.iter().find(|&&line| line >= scheme.len()) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "line {} of \"{event}\" does not exist: the schedule has {} lines",
+                        line + 1,
+                        scheme.len()
+                    ),
+                ));
+            }
+            if !names.contains(&*event) {
+                unknown.push(event.clone());
+            }
+            allowed.entry(Arc::from(event)).or_default().extend(lines);
+        }
+        Ok((allowed, unknown))
+    }
+
+    // Lines are numbered from 1, as in reports.
+    pub fn deserialize_csv<R>(reader: &mut csv::Reader<R>) -> io::Result<Self>
+    where
+        R: io::Read,
+    {
+        let mut events = Vec::new();
+        for line in reader.records() {
+            let line = line?;
+            let mut fields = line.iter().map(str::trim).filter(|f| !f.is_empty());
+            let Some(event) = fields.next() else {
+                continue;
+            };
+            let lines = fields
+                .map(|f| match f.parse::<usize>() {
+                    Ok(0) => Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "field \"line\" is numbered from 1",
+                    )),
+                    Ok(line) => Ok(line - 1),
+                    Err(_) => Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "field \"line\" expected type: <integer>",
+                    )),
+                })
+                .collect::<Result<Vec<usize>>>()?;
+            events.push((Box::from(event), lines));
+        }
+        Ok(Self(events))
+    }
+}
+
+#[derive(Debug)]
+pub struct CapacityModel(Vec<(Box<str>, usize)>);
+
+impl CapacityModel {
+    pub fn normalize(&mut self, normalizer: &mut Normalizer) {
+        for (name, _) in &mut self.0 {
+            *name = normalizer.canonical(name);
+        }
+    }
+
+    // Capacities by the id of each name in `registry`, and the names not in it.
+    pub fn resolve(self, registry: &Registry) -> (BTreeMap<Id, usize>, Vec<Box<str>>) {
+        let mut capacities = BTreeMap::new();
+        let mut unknown = Vec::new();
+        for (name, capacity) in self.0 {
+            match registry.get(&name) {
+                Some(id) => {
+                    capacities.insert(id, capacity);
+                }
+                None => unknown.push(name),
+            }
+        }
+        (capacities, unknown)
+    }
+
+    pub fn deserialize_csv<R>(reader: &mut csv::Reader<R>) -> io::Result<Self>
+    where
+        R: io::Read,
+    {
+        let mut capacities = Vec::new();
+        for line in reader.records() {
+            let line = line?;
+            let mut fields = line.iter().map(str::trim).filter(|f| !f.is_empty());
+            let Some(name) = fields.next() else {
+                continue;
+            };
+            let capacity = fields
+                .next()
+                .ok_or(Error::new(
+                    ErrorKind::InvalidInput,
+                    "missing field: \"capacity\"",
+                ))?
+                .parse::<usize>()
+                .map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        "field \"capacity\" expected type: <integer>",
+                    )
+                })?;
+            if capacity == 0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "field \"capacity\" must be at least 1",
+                ));
+            }
+            capacities.push((Box::from(name), capacity));
+        }
+        Ok(Self(capacities))
+    }
+}
+
+// Which differences between two spellings of a leader or resource name are ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalization {
+    pub fold_case: bool,
+    pub collapse_whitespace: bool,
+    pub unicode: bool,
+}
+
+impl FromStr for Normalization {
+    type Err = std::io::Error;
+
+    // `all`, or any of `case`, `whitespace` and `unicode` separated by commas.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut normalization = Normalization::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part {
+                "all" => {
+                    normalization = Normalization {
+                        fold_case: true,
+                        collapse_whitespace: true,
+                        unicode: true,
+                    }
+                }
+                "case" => normalization.fold_case = true,
+                "whitespace" => normalization.collapse_whitespace = true,
+                "unicode" => normalization.unicode = true,
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("unknown normalization: \"{part}\""),
+                    ));
+                }
+            }
+        }
+        Ok(normalization)
+    }
+}
+
+#[derive(Debug)]
+pub struct AliasModel(Vec<(Box<str>, Vec<Box<str>>)>);
+
+impl AliasModel {
+    pub fn deserialize_csv<R>(reader: &mut csv::Reader<R>) -> io::Result<Self>
+    where
+        R: io::Read,
+    {
+        let mut aliases = Vec::new();
+        for line in reader.records() {
+            let line = line?;
+            let mut fields = line.iter().map(str::trim).filter(|f| !f.is_empty());
+            let Some(canonical) = fields.next() else {
+                continue;
+            };
+            aliases.push((Box::from(canonical), fields.map(Box::from).collect()));
+        }
+        Ok(Self(aliases))
+    }
+}
+
+// Maps every spelling of a name to one canonical name: the alias target if the file lists
+// it, otherwise the first spelling seen with the same normalized form.
+#[derive(Debug, Default)]
+pub struct Normalizer {
+    normalization: Normalization,
+    aliases: HashMap<String, Box<str>>,
+    names: HashMap<String, Box<str>>,
+    merged: BTreeMap<Box<str>, BTreeSet<Box<str>>>,
+}
+
+impl Normalizer {
+    pub fn new(normalization: Normalization, aliases: Option<AliasModel>) -> Self {
+        let mut normalizer = Normalizer {
+            normalization,
+            ..Default::default()
+        };
+        for (canonical, variants) in aliases.into_iter().flat_map(|a| a.0) {
+            for name in variants.iter().chain([&canonical]) {
+                let key = normalizer.key(name);
+                normalizer.aliases.insert(key, canonical.clone());
+            }
+        }
+        normalizer
+    }
+
+    fn key(&self, name: &str) -> String {
+        let mut key = name.trim().to_string();
+        if self.normalization.unicode {
+            key = ComposingNormalizerBorrowed::new_nfkc()
+                .normalize(&key)
+                .into_owned();
+        }
+        if self.normalization.collapse_whitespace {
+            key = key.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        if self.normalization.fold_case {
+            key = key.to_lowercase();
+        }
+        key
+    }
+
+    pub fn canonical(&mut self, name: &str) -> Box<str> {
+        let key = self.key(name);
+        let canonical = match self.aliases.get(&key) {
+            Some(canonical) => canonical.clone(),
+            None => {
+                let collapse = self.normalization.collapse_whitespace;
+                self.names
+                    .entry(key)
+                    .or_insert_with(|| match collapse {
+                        true => name.split_whitespace().collect::<Vec<_>>().join(" ").into(),
+                        false => name.trim().into(),
+                    })
+                    .clone()
+            }
+        };
+        if *canonical != *name.trim() {
+            self.merged
+                .entry(canonical.clone())
+                .or_default()
+                .insert(name.trim().into());
+        }
+        canonical
+    }
+
+    // Spellings replaced while loading, by the name they were merged into.
+    pub fn merged(&self) -> &BTreeMap<Box<str>, BTreeSet<Box<str>>> {
+        &self.merged
     }
 }
@@ -0,0 +1,354 @@
+use {
+    crate::Schedule,
+    rand::{Rng, RngExt},
+    std::{
+        collections::BTreeMap,
+        io::{Error, ErrorKind},
+        ops::Range,
+        str::FromStr,
+        sync::Arc,
+    },
+};
+
+// A change to the order of one line, or a movable event going to another line. Inserting an
+// event elsewhere and swapping adjacent blocks are both rotations of the events between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    Swap {
+        line: usize,
+        a: usize,
+        b: usize,
+    },
+    // `scheme[line][start..end].rotate_left(shift)`
+    Rotate {
+        line: usize,
+        start: usize,
+        end: usize,
+        shift: usize,
+    },
+    // Event `i` of line `from` becomes event `j` of line `to`.
+    Relocate {
+        from: usize,
+        i: usize,
+        to: usize,
+        j: usize,
+    },
+}
+
+impl Move {
+    pub fn inverse(self) -> Move {
+        match self {
+            Move::Swap { .. } => self,
+            Move::Rotate {
+                line,
+                start,
+                end,
+                shift,
+            } => Move::Rotate {
+                line,
+                start,
+                end,
+                shift: end - start - shift,
+            },
+            Move::Relocate { from, i, to, j } => Move::Relocate {
+                from: to,
+                i: j,
+                to: from,
+                j: i,
+            },
+        }
+    }
+}
+
+// Relative probabilities of the move kinds the annealer proposes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mix {
+    pub swap: f64,
+    pub insert: f64,
+    pub rotate: f64,
+    pub block_swap: f64,
+    pub relocate: f64,
+}
+
+impl Default for Mix {
+    fn default() -> Self {
+        Self {
+            swap: 1.0,
+            insert: 0.0,
+            rotate: 0.0,
+            block_swap: 0.0,
+            relocate: 0.0,
+        }
+    }
+}
+
+impl Mix {
+    // Swaps, plus relocations as often when `schedule` has movable events.
+    pub fn default_for(schedule: &Schedule) -> Self {
+        let movable = schedule.scheme.iter().flatten().any(|e| e.movable);
+        Self {
+            relocate: if movable { 1.0 } else { 0.0 },
+            ..Default::default()
+        }
+    }
+}
+
+impl FromStr for Mix {
+    type Err = Error;
+
+    // `swap=2,insert=1`: kinds left out get probability 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = Mix {
+            swap: 0.0,
+            insert: 0.0,
+            rotate: 0.0,
+            block_swap: 0.0,
+            relocate: 0.0,
+        };
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (kind, weight) = part.split_once('=').unwrap_or((part, "1"));
+            let weight: f64 = weight
+                .trim()
+                .parse()
+                .ok()
+                .filter(|w: &f64| *w >= 0.0)
+                .ok_or(Error::new(ErrorKind::InvalidData, "invalid move weight"))?;
+            match kind.trim() {
+                "swap" => mix.swap = weight,
+                "insert" => mix.insert = weight,
+                "rotate" => mix.rotate = weight,
+                "block" | "block-swap" => mix.block_swap = weight,
+                "relocate" => mix.relocate = weight,
+                _ => return Err(Error::new(ErrorKind::InvalidData, "unknown move kind")),
+            }
+        }
+        if mix.swap + mix.insert + mix.rotate + mix.block_swap + mix.relocate <= 0.0 {
+            return Err(Error::new(ErrorKind::InvalidData, "no move kind enabled"));
+        }
+        Ok(mix)
+    }
+}
+
+// Allowed lines by event name.
+pub type Allowed = BTreeMap<Arc<str>, Vec<usize>>;
+
+// Where movable events may go: a line takes events while its length stays within its
+// capacity, and events named in `allowed` only go to the lines listed for them.
+#[derive(Debug, Clone, Default)]
+pub struct Relocation {
+    pub capacity: Vec<usize>,
+    pub allowed: Allowed,
+}
+
+impl Schedule {
+    pub fn can_apply(&self, mv: Move) -> bool {
+        match mv {
+            Move::Swap { line, a, b } => self.can_swap(line, a, b),
+            Move::Rotate {
+                line,
+                start,
+                end,
+                shift,
+            } => {
+                start < end
+                    && shift < end - start
+                    && !self.scheme[line][start..end].iter().any(|e| e.pinned)
+            }
+            Move::Relocate { from, i, to, j } => {
+                let event = &self.scheme[from][i];
+                let capacity = self.relocation.capacity.get(to).copied();
+                from != to
+                    && event.movable
+                    && !event.pinned
+                    && self
+                        .relocation
+                        .allowed
+                        .get(&event.name)
+                        .is_none_or(|lines| lines.contains(&to))
+                    && capacity.is_none_or(|c| self.event[to].len() + event.len <= c)
+                    && !self.scheme[from][i..].iter().any(|e| e.pinned)
+                    && !self.scheme[to][j..].iter().any(|e| e.pinned)
+            }
+        }
+    }
+
+    // The most slots any line holds or may grow to through relocation.
+    pub fn reach(&self) -> usize {
+        let capacity = self.relocation.capacity.iter().copied().max();
+        self.width().max(capacity.unwrap_or_default())
+    }
+
+    // Slots the fixed events of `line` may end up in.
+    pub fn room(&self, line: usize) -> usize {
+        let width = self.idx[line].last().copied().unwrap_or_default();
+        if !self.scheme.iter().flatten().any(|e| e.movable) {
+            return width;
+        }
+        let capacity = self.relocation.capacity.get(line).copied();
+        width.max(capacity.unwrap_or_else(|| self.reach()))
+    }
+
+    pub(crate) fn apply(&mut self, mv: Move) {
+        match mv {
+            Move::Swap { line, a, b } => self.swap(line, a, b),
+            Move::Rotate {
+                line,
+                start,
+                end,
+                shift,
+            } => self.rotate(line, start..end, shift),
+            Move::Relocate { from, i, to, j } => self.relocate(from, i, to, j),
+        }
+    }
+
+    // Undoes `mv`, the last move applied, which changed the cost, from what `change_slots`
+    // kept of it.
+    pub(crate) fn revert(&mut self, mv: Move) {
+        self.buffers.reverting = true;
+        self.apply(mv.inverse());
+        self.buffers.reverting = false;
+    }
+
+    fn rotate(&mut self, line: usize, events: Range<usize>, shift: usize) {
+        if shift == 0 {
+            return;
+        }
+        let slots = self.idx[line][events.start]..self.idx[line][events.end];
+        self.change_slots(&[slots], |s| {
+            s.scheme[line][events.clone()].rotate_left(shift);
+            s.reindex(line, events);
+        });
+    }
+
+    fn relocate(&mut self, from: usize, i: usize, to: usize, j: usize) {
+        let len = self.scheme[from][i].len;
+        let start = self.idx[from][i].min(self.idx[to][j]);
+        let end = self.event[from].len().max(self.event[to].len() + len);
+        let slots = start..end;
+        self.change_slots(&[slots], |s| {
+            let event = s.scheme[from].remove(i);
+            s.scheme[to].insert(j, event);
+
+            let from_len = s.event[from].len() - len;
+            s.event[from].truncate(from_len);
+            s.idx[from].pop();
+            let to_len = s.event[to].len() + len;
+            s.event[to].resize(to_len, 0);
+            s.idx[to].push(0);
+
+            s.reindex(from, i..s.scheme[from].len());
+            s.reindex(to, j..s.scheme[to].len());
+        });
+    }
+
+    // Picks an event (a blamed one when `greedily`) and a move of a kind drawn from `mix`.
+    pub(crate) fn propose<R>(&self, rng: &mut R, greedily: bool, mix: &Mix) -> Option<Move>
+    where
+        R: Rng + ?Sized,
+    {
+        let (line, a) = if greedily && !self.collisions.is_empty() {
+            *self
+                .collisions
+                .get_index(rng.random_range(0..self.collisions.len()))
+                .unwrap()
+                .0
+        } else {
+            let line = rng.random_range(0..self.scheme.len());
+            if self.scheme[line].is_empty() {
+                return None;
+            }
+            (line, rng.random_range(0..self.scheme[line].len()))
+        };
+        let n = self.scheme[line].len();
+
+        let others = mix.insert + mix.rotate + mix.block_swap + mix.relocate;
+        let mut pick = if others > 0.0 {
+            rng.random_range(0.0..mix.swap + others)
+        } else {
+            0.0
+        };
+        if pick < mix.swap || others <= 0.0 {
+            return Some(Move::Swap {
+                line,
+                a,
+                b: rng.random_range(0..n),
+            });
+        }
+        pick -= mix.swap;
+
+        if pick < mix.insert {
+            let to = rng.random_range(0..n);
+            return match a.cmp(&to) {
+                std::cmp::Ordering::Equal => None,
+                std::cmp::Ordering::Less => Some(Move::Rotate {
+                    line,
+                    start: a,
+                    end: to + 1,
+                    shift: 1,
+                }),
+                std::cmp::Ordering::Greater => Some(Move::Rotate {
+                    line,
+                    start: to,
+                    end: a + 1,
+                    shift: a - to,
+                }),
+            };
+        }
+        pick -= mix.insert;
+
+        if pick < mix.rotate {
+            let b = rng.random_range(0..n);
+            let (start, end) = (a.min(b), a.max(b) + 1);
+            if end - start < 2 {
+                return None;
+            }
+            return Some(Move::Rotate {
+                line,
+                start,
+                end,
+                shift: rng.random_range(1..end - start),
+            });
+        }
+
+        pick -= mix.rotate;
+
+        if pick < mix.block_swap {
+            // Blocks `a..mid` and `mid..end` of equal total length trade places.
+            let events = &self.scheme[line];
+            let mid = rng.random_range(a + 1..=n);
+            let len: usize = events[a..mid].iter().map(|e| e.len).sum();
+            let mut rest = 0;
+            for end in mid + 1..=n {
+                rest += events[end - 1].len;
+                if rest >= len {
+                    return (rest == len).then_some(Move::Rotate {
+                        line,
+                        start: a,
+                        end,
+                        shift: mid - a,
+                    });
+                }
+            }
+            return None;
+        }
+
+        let event = &self.scheme[line][a];
+        if !event.movable {
+            return None;
+        }
+        let to = match self.relocation.allowed.get(&event.name) {
+            Some(lines) if lines.is_empty() => return None,
+            Some(lines) => lines[rng.random_range(0..lines.len())],
+            None => rng.random_range(0..self.scheme.len()),
+        };
+        if to == line || to >= self.scheme.len() {
+            return None;
+        }
+        Some(Move::Relocate {
+            from: line,
+            i: a,
+            to,
+            j: rng.random_range(0..=self.scheme[to].len()),
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use {
+    crate::{Options, Outcome, Progress, Schedule},
+    rand::{RngExt, SeedableRng, rngs::StdRng},
+    std::{
+        ops::ControlFlow,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    },
+};
+
+pub struct Run {
+    pub schedule: Schedule,
+    pub outcome: Outcome,
+    pub job: usize,
+    pub seed: u64,
+}
+
+pub fn job_seeds(seed: u64, jobs: usize) -> Vec<u64> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..jobs).map(|_| rng.random()).collect()
+}
+
+// Runs `jobs` independent chains from `schedule`, each seeded from `seed`, and returns the
+// one with the lowest best cost. Ties go to the lowest job index, and a chain is only cut
+// short once a lower-indexed one has reached zero, so the winner depends on `seed` alone.
+pub fn optimize<F>(
+    schedule: &Schedule,
+    jobs: usize,
+    seed: u64,
+    options: &Options,
+    tick_func: F,
+) -> Run
+where
+    F: Fn(usize, &Progress) -> ControlFlow<()> + Sync,
+{
+    let jobs = jobs.max(1);
+    let solved = AtomicUsize::new(usize::MAX);
+
+    let runs: Vec<Run> = thread::scope(|scope| {
+        let handles: Vec<_> = job_seeds(seed, jobs)
+            .into_iter()
+            .enumerate()
+            .map(|(job, seed)| {
+                let mut schedule = schedule.clone();
+                let solved = &solved;
+                let tick_func = &tick_func;
+                scope.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    let outcome = schedule.optimize(&mut rng, options, |progress| {
+                        if progress.best_cost.is_zero() {
+                            solved.fetch_min(job, Ordering::Relaxed);
+                        }
+                        if solved.load(Ordering::Relaxed) < job {
+                            return ControlFlow::Break(());
+                        }
+                        tick_func(job, progress)
+                    });
+                    Run {
+                        schedule,
+                        outcome,
+                        job,
+                        seed,
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let objective = schedule.objective;
+    runs.into_iter()
+        .reduce(|best, run| {
+            if run
+                .outcome
+                .best_cost
+                .delta(&best.outcome.best_cost, objective)
+                < 0.0
+            {
+                run
+            } else {
+                best
+            }
+        })
+        .unwrap()
+}
@@ -0,0 +1,46 @@
+use {
+    crate::{Event, Id},
+    std::{collections::HashMap, sync::Arc},
+};
+
+// Leader and resource names interned to dense ids, so constraints can count in flat arrays
+// indexed by `Participant::id`.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    names: Vec<Arc<str>>,
+    ids: HashMap<Arc<str>, Id>,
+}
+
+impl Registry {
+    pub fn intern(&mut self, name: &Arc<str>) -> Id {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.clone());
+        self.ids.insert(name.clone(), id);
+        id
+    }
+
+    pub fn get(&self, name: &str) -> Option<Id> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: Id) -> &str {
+        &self.names[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub(crate) fn register(&mut self, event: &mut Event) {
+        for participant in event.participants.iter_mut().chain(&mut event.resources) {
+            participant.id = self.intern(&participant.name);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use {
+    crate::{Cost, Event, Outcome, Progress, Schedule},
+    rand::{Rng, RngExt},
+    std::{
+        collections::HashMap,
+        ops::ControlFlow,
+        time::{Duration, Instant},
+    },
+};
+
+pub const TENURE_DEFAULT: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub tenure: usize,
+    pub iterations: usize,
+    pub shuffling: bool,
+    pub time_limit: Option<Duration>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            tenure: TENURE_DEFAULT,
+            iterations: crate::AGING_OPT_DEFAULT,
+            shuffling: false,
+            time_limit: None,
+        }
+    }
+}
+
+// Tabu search: every iteration picks an event (a blamed one when there are collisions),
+// tries swapping it with each other event of its line and keeps the best swap that is not
+// tabu. Undoing a swap is tabu for `tenure` iterations unless it beats the best cost
+// found so far. `schedule` is left holding that best arrangement.
+pub fn optimize<R, F>(
+    schedule: &mut Schedule,
+    rng: &mut R,
+    options: &Options,
+    mut tick_func: F,
+) -> Outcome
+where
+    R: Rng + ?Sized,
+    F: FnMut(&Progress) -> ControlFlow<()>,
+{
+    let deadline = options.time_limit.map(|limit| Instant::now() + limit);
+
+    schedule.update();
+    if schedule.scheme.is_empty() || schedule.cost.is_zero() {
+        return Outcome {
+            final_cost: schedule.cost,
+            best_cost: schedule.cost,
+            iterations: 0,
+        };
+    }
+
+    if options.shuffling {
+        schedule.shuffle(rng);
+        schedule.update();
+    }
+
+    let objective = schedule.objective;
+    let mut best_cost = schedule.cost;
+    let mut best: Vec<Vec<Event>> = schedule.scheme.clone();
+    let mut tabu: HashMap<(usize, usize, usize), usize> = HashMap::new();
+
+    let mut progress = Progress {
+        iteration: 0,
+        temperature: 0.0,
+        cost: schedule.cost,
+        best_cost,
+        accepted: 0,
+        rejected: 0,
+    };
+
+    for iteration in 0..options.iterations {
+        progress.iteration = iteration + 1;
+
+        let (line, a) = if schedule.collisions.is_empty() {
+            let line = rng.random_range(0..schedule.scheme.len());
+            if schedule.scheme[line].is_empty() {
+                continue;
+            }
+            (line, rng.random_range(0..schedule.scheme[line].len()))
+        } else {
+            *schedule
+                .collisions
+                .get_index(rng.random_range(0..schedule.collisions.len()))
+                .unwrap()
+                .0
+        };
+
+        let mut chosen: Option<(usize, Cost)> = None;
+        for b in 0..schedule.scheme[line].len() {
+            if a == b || !schedule.can_swap(line, a, b) {
+                continue;
+            }
+            schedule.swap(line, a, b);
+            let cost = schedule.cost;
+            schedule.swap(line, a, b);
+
+            let key = (line, a.min(b), a.max(b));
+            let aspires = cost.delta(&best_cost, objective) < 0.0;
+            if tabu.get(&key).is_some_and(|&until| until > iteration) && !aspires {
+                progress.rejected += 1;
+                continue;
+            }
+            if chosen.is_none_or(|(_, chosen)| cost.delta(&chosen, objective) < 0.0) {
+                chosen = Some((b, cost));
+            }
+        }
+
+        if let Some((b, _)) = chosen {
+            schedule.swap(line, a, b);
+            tabu.insert((line, a.min(b), a.max(b)), iteration + options.tenure);
+            progress.accepted += 1;
+
+            if schedule.cost.delta(&best_cost, objective) < 0.0 {
+                best_cost = schedule.cost;
+                best = schedule.scheme.clone();
+            }
+        }
+
+        progress.cost = schedule.cost;
+        progress.best_cost = best_cost;
+        if tick_func(&progress).is_break()
+            || best_cost.is_zero()
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            break;
+        }
+    }
+
+    let final_cost = schedule.cost;
+    schedule.scheme = best;
+    schedule.update();
+
+    Outcome {
+        final_cost,
+        best_cost: schedule.cost,
+        iterations: progress.iteration,
+    }
+}
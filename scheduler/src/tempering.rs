@@ -0,0 +1,174 @@
+use {
+    crate::{Event, Outcome, Progress, Schedule, Step, moves::Mix},
+    rand::{Rng, RngExt},
+    std::{
+        ops::ControlFlow,
+        time::{Duration, Instant},
+    },
+};
+
+pub const REPLICAS_DEFAULT: usize = 8;
+pub const T_MIN_DEFAULT: f64 = 0.05;
+pub const T_MAX_DEFAULT: f64 = 2.0;
+pub const EXCHANGE_INTERVAL_DEFAULT: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub replicas: usize,
+    pub t_min: f64,
+    pub t_max: f64,
+    pub steps: usize,
+    pub exchange_interval: usize,
+    pub shuffling: bool,
+    pub greedily: bool,
+    pub moves: Mix,
+    pub time_limit: Option<Duration>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            replicas: REPLICAS_DEFAULT,
+            t_min: T_MIN_DEFAULT,
+            t_max: T_MAX_DEFAULT,
+            steps: crate::AGING_OPT_DEFAULT,
+            exchange_interval: EXCHANGE_INTERVAL_DEFAULT,
+            shuffling: false,
+            greedily: false,
+            moves: Mix::default(),
+            time_limit: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn temperatures(&self) -> Vec<f64> {
+        let n = self.replicas.max(1);
+        if n == 1 {
+            return vec![self.t_min];
+        }
+        let ratio = (self.t_max / self.t_min).powf(1.0 / (n - 1) as f64);
+        (0..n).map(|k| self.t_min * ratio.powi(k as i32)).collect()
+    }
+}
+
+// Replica exchange: one copy of `schedule` per temperature of a geometric ladder, each doing
+// `exchange_interval` Metropolis steps between attempts to swap neighbouring replicas.
+// `schedule` is left holding the best arrangement any replica visited.
+pub fn optimize<R, F>(
+    schedule: &mut Schedule,
+    rng: &mut R,
+    options: &Options,
+    mut tick_func: F,
+) -> Outcome
+where
+    R: Rng + ?Sized,
+    F: FnMut(&Progress) -> ControlFlow<()>,
+{
+    let deadline = options.time_limit.map(|limit| Instant::now() + limit);
+
+    schedule.update();
+    if schedule.scheme.is_empty() || schedule.cost.is_zero() {
+        return Outcome {
+            final_cost: schedule.cost,
+            best_cost: schedule.cost,
+            iterations: 0,
+        };
+    }
+
+    let temperatures = options.temperatures();
+    let mut replicas: Vec<Schedule> = temperatures
+        .iter()
+        .map(|_| {
+            let mut replica = schedule.clone();
+            if options.shuffling {
+                replica.shuffle(rng);
+                replica.update();
+            }
+            replica
+        })
+        .collect();
+
+    let objective = schedule.objective;
+    let mut best_cost = schedule.cost;
+    let mut best: Option<Vec<Vec<Event>>> = None;
+    for replica in &replicas {
+        if replica.cost.delta(&best_cost, objective) < 0.0 {
+            best_cost = replica.cost;
+            best = Some(replica.scheme.clone());
+        }
+    }
+    // A shuffled replica may already be a solution.
+    if best_cost.is_zero() {
+        let final_cost = replicas[0].cost;
+        if let Some(best) = best {
+            schedule.scheme = best;
+        }
+        schedule.update();
+        return Outcome {
+            final_cost,
+            best_cost: schedule.cost,
+            iterations: 0,
+        };
+    }
+
+    let mut progress = Progress {
+        iteration: 0,
+        temperature: temperatures[0],
+        cost: replicas[0].cost,
+        best_cost,
+        accepted: 0,
+        rejected: 0,
+    };
+
+    let interval = options.exchange_interval.max(1);
+    'rounds: for _ in 0..options.steps.div_ceil(interval) {
+        for (replica, &temperature) in replicas.iter_mut().zip(&temperatures) {
+            for _ in 0..interval {
+                match replica.step(rng, temperature, options.greedily, &options.moves) {
+                    Step::Skipped => {}
+                    Step::Rejected => progress.rejected += 1,
+                    Step::Accepted { .. } => {
+                        progress.accepted += 1;
+                        if replica.cost.delta(&best_cost, objective) < 0.0 {
+                            best_cost = replica.cost;
+                            best = Some(replica.scheme.clone());
+                            if best_cost.is_zero() {
+                                break 'rounds;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for k in (0..replicas.len() - 1).rev() {
+            let delta = replicas[k].cost.delta(&replicas[k + 1].cost, objective);
+            let beta = 1.0 / temperatures[k] - 1.0 / temperatures[k + 1];
+            if rng.random_bool(f64::exp(delta * beta).min(1.0)) {
+                replicas.swap(k, k + 1);
+            }
+        }
+
+        progress.iteration += interval;
+        progress.cost = replicas[0].cost;
+        progress.best_cost = best_cost;
+        if tick_func(&progress).is_break()
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            break;
+        }
+    }
+
+    let final_cost = replicas[0].cost;
+    if let Some(best) = best {
+        schedule.scheme = best;
+    }
+    schedule.update();
+
+    Outcome {
+        final_cost,
+        best_cost: schedule.cost,
+        iterations: progress.iteration,
+    }
+}